@@ -0,0 +1,261 @@
+use legumegb_rs::{BusAccess, Gameboy};
+use serde::Deserialize;
+use std::{
+    env, fs,
+    num::Wrapping as W,
+    path::{Path, PathBuf},
+    process,
+};
+
+//runs SingleStepTests style json files (one file per opcode, each holding a list of cases
+//with an initial cpu + ram state, the expected final state and the bus activity per cycle)
+//against the cpu on a flat 64k ram bus and reports what didn't match
+
+const DEFAULT_MAX_FAILURES_SHOWN: usize = 3;
+
+#[derive(Deserialize)]
+struct CpuState {
+    a: u8,
+    b: u8,
+    c: u8,
+    d: u8,
+    e: u8,
+    f: u8,
+    h: u8,
+    l: u8,
+    pc: u16,
+    sp: u16,
+    ime: Option<u8>,
+    ram: Vec<(u16, u8)>,
+}
+
+#[derive(Deserialize)]
+struct TestCase {
+    name: String,
+    initial: CpuState,
+    #[serde(rename = "final")]
+    expected: CpuState,
+    //one [address, value, "r-m" / "-wm"] entry per m-cycle, null for cycles without bus activity
+    cycles: Vec<Option<(u16, Option<u8>, String)>>,
+}
+
+struct OpcodeResult {
+    name: String,
+    passed: usize,
+    total: usize,
+    failures: Vec<String>,
+}
+
+fn find_test_files(path: &Path) -> Vec<PathBuf> {
+    if path.is_file() {
+        return vec![path.to_path_buf()];
+    }
+
+    let mut files: Vec<PathBuf> = match fs::read_dir(path) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")))
+            .collect(),
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", path.display(), e);
+            Vec::new()
+        }
+    };
+    files.sort();
+    files
+}
+
+fn flag_names(f: u8) -> String {
+    let mut names = String::new();
+    for (bit, name) in [(0x80, 'Z'), (0x40, 'N'), (0x20, 'H'), (0x10, 'C')] {
+        names.push(if f & bit != 0 { name } else { '-' });
+    }
+    names
+}
+
+fn expected_accesses(case: &TestCase) -> Vec<BusAccess> {
+    let mut accesses = Vec::new();
+    for (cycle, entry) in case.cycles.iter().enumerate() {
+        if let Some((addr, Some(value), kind)) = entry {
+            let write = kind.contains('w');
+            if write || kind.starts_with('r') {
+                accesses.push(BusAccess { cycle: cycle as u32, addr: *addr, value: *value, write });
+            }
+        }
+    }
+    accesses
+}
+
+fn describe_access(access: Option<&BusAccess>) -> String {
+    match access {
+        Some(a) if a.write => format!("cycle {} write {:#04x} to {:#06x}", a.cycle, a.value, a.addr),
+        Some(a) => format!("cycle {} read {:#04x} from {:#06x}", a.cycle, a.value, a.addr),
+        None => String::from("nothing"),
+    }
+}
+
+fn run_case(case: &TestCase) -> Vec<String> {
+    let mut gb = Gameboy::new_flat_bus();
+    let initial = &case.initial;
+
+    let reg = gb.get_registers_mut();
+    reg.a = W(initial.a);
+    reg.set_f(W(initial.f));
+    reg.b = W(initial.b);
+    reg.c = W(initial.c);
+    reg.d = W(initial.d);
+    reg.e = W(initial.e);
+    reg.h = W(initial.h);
+    reg.l = W(initial.l);
+    gb.set_pc(initial.pc);
+    gb.set_sp(initial.sp);
+    gb.set_ime(initial.ime.unwrap_or(0) != 0);
+    for (addr, value) in &initial.ram {
+        gb.write_byte_raw(W(*addr), W(*value));
+    }
+
+    if let Err(e) = legumegb_rs::step(&mut gb) {
+        return vec![format!("cpu {}", e)];
+    }
+    if let Some(lockup) = gb.get_lockup() {
+        return vec![format!("cpu locked up on {}", lockup)];
+    }
+
+    let mut mismatches = Vec::new();
+    let expected = &case.expected;
+    let reg = gb.get_registers();
+    for (name, got, want) in [
+        ("a", reg.a.0, expected.a),
+        ("b", reg.b.0, expected.b),
+        ("c", reg.c.0, expected.c),
+        ("d", reg.d.0, expected.d),
+        ("e", reg.e.0, expected.e),
+        ("h", reg.h.0, expected.h),
+        ("l", reg.l.0, expected.l),
+    ] {
+        if got != want {
+            mismatches.push(format!("{} = {:#04x}, expected {:#04x}", name, got, want));
+        }
+    }
+
+    let flags = reg.get_f().0;
+    if flags != expected.f & 0xf0 {
+        mismatches.push(format!("flags = {}, expected {}", flag_names(flags), flag_names(expected.f)));
+    }
+    if gb.get_pc() != expected.pc {
+        mismatches.push(format!("pc = {:#06x}, expected {:#06x}", gb.get_pc(), expected.pc));
+    }
+    if gb.get_sp() != expected.sp {
+        mismatches.push(format!("sp = {:#06x}, expected {:#06x}", gb.get_sp(), expected.sp));
+    }
+    if let Some(ime) = expected.ime {
+        if gb.get_ime() != (ime != 0) {
+            mismatches.push(format!("ime = {}, expected {}", gb.get_ime() as u8, ime));
+        }
+    }
+
+    let m_cycles = gb.get_last_step_cycles() / 4;
+    if m_cycles as usize != case.cycles.len() {
+        mismatches.push(format!("took {} m-cycles, expected {}", m_cycles, case.cycles.len()));
+    }
+
+    //only the first difference, everything after it is usually shifted along
+    let accesses = gb.take_bus_accesses();
+    let expected_accesses = expected_accesses(case);
+    let bus_len = accesses.len().max(expected_accesses.len());
+    if let Some(i) = (0..bus_len).find(|i| accesses.get(*i) != expected_accesses.get(*i)) {
+        mismatches.push(format!(
+            "bus did {}, expected {}",
+            describe_access(accesses.get(i)),
+            describe_access(expected_accesses.get(i))
+        ));
+    }
+
+    for (addr, value) in &expected.ram {
+        let got = gb.read_byte_raw(W(*addr)).0;
+        if got != *value {
+            mismatches.push(format!("[{:#06x}] = {:#04x}, expected {:#04x}", addr, got, value));
+        }
+    }
+
+    mismatches
+}
+
+fn run_file(path: &Path, max_failures: usize) -> Result<OpcodeResult, String> {
+    let data = fs::read(path).map_err(|e| e.to_string())?;
+    let cases: Vec<TestCase> = serde_json::from_slice(&data).map_err(|e| e.to_string())?;
+
+    let mut result = OpcodeResult {
+        name: path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default(),
+        passed: 0,
+        total: cases.len(),
+        failures: Vec::new(),
+    };
+
+    for case in &cases {
+        let mismatches = run_case(case);
+        if mismatches.is_empty() {
+            result.passed += 1;
+        } else if result.failures.len() < max_failures {
+            result.failures.push(format!("{}: {}", case.name, mismatches.join(", ")));
+        }
+    }
+    Ok(result)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("Arguments: {} <test directory or json file> [--max-failures <n>]", args[0]);
+        process::exit(2);
+    }
+
+    let mut max_failures = DEFAULT_MAX_FAILURES_SHOWN;
+    let mut extra_args = args[2..].iter();
+    while let Some(arg) = extra_args.next() {
+        match arg.as_str() {
+            "--max-failures" => match extra_args.next().and_then(|v| v.parse().ok()) {
+                Some(n) => max_failures = n,
+                None => {
+                    eprintln!("--max-failures needs a number of cases to show per opcode");
+                    process::exit(2);
+                }
+            },
+            _ => {
+                eprintln!("Unknown option: {}", arg);
+                process::exit(2);
+            }
+        }
+    }
+
+    let files = find_test_files(Path::new(&args[1]));
+    if files.is_empty() {
+        eprintln!("No .json test files found in {}", args[1]);
+        process::exit(2);
+    }
+
+    let mut opcodes_passed = 0;
+    let mut opcodes_total = 0;
+    for file in &files {
+        opcodes_total += 1;
+        match run_file(file, max_failures) {
+            Ok(result) if result.passed == result.total => {
+                opcodes_passed += 1;
+                println!("{:<8} ok    {}/{}", result.name, result.passed, result.total);
+            }
+            Ok(result) => {
+                println!("{:<8} FAIL  {}/{}", result.name, result.passed, result.total);
+                for failure in &result.failures {
+                    println!("    {}", failure);
+                }
+            }
+            Err(e) => println!("{:<8} ERROR {}", file.display(), e),
+        }
+    }
+
+    println!("{}/{} opcodes passed", opcodes_passed, opcodes_total);
+    if opcodes_passed != opcodes_total {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,121 @@
+use legumegb_rs::{disassemble, SymbolTable};
+use std::{env, fs, path::Path, process};
+
+//disassembles a rom file bank by bank. bank 0 is shown at 0x0000-0x3fff and every other bank
+//at 0x4000-0x7fff, where the cpu would see it once it is switched in. labels come from a .sym
+//file next to the rom unless another one is given
+
+const BANK_SIZE: usize = 0x4000;
+
+fn parse_number(text: &str) -> Option<usize> {
+    let hex = text.strip_prefix("0x").or_else(|| text.strip_prefix('$'));
+    match hex {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+//a single bank or an inclusive first-last range
+fn parse_bank_range(text: &str) -> Option<(usize, usize)> {
+    match text.split_once('-') {
+        Some((first, last)) => Some((parse_number(first)?, parse_number(last)?)),
+        None => parse_number(text).map(|bank| (bank, bank)),
+    }
+}
+
+fn bank_base_addr(bank: usize) -> usize {
+    if bank == 0 { 0x0000 } else { BANK_SIZE }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!(
+            "Arguments: {} <rom file> [--bank <n> or <first>-<last>] [--start <addr>] [--end <addr>] [--sym <symbol file>]",
+            args[0]
+        );
+        process::exit(2);
+    }
+
+    let rom = match fs::read(&args[1]) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", args[1], e);
+            process::exit(2);
+        }
+    };
+    let bank_count = rom.len().div_ceil(BANK_SIZE);
+
+    let mut banks = (0, bank_count.saturating_sub(1));
+    let mut start = None;
+    let mut end = None;
+    let mut sym_path = Path::new(&args[1]).with_extension("sym");
+    let mut extra_args = args[2..].iter();
+    while let Some(arg) = extra_args.next() {
+        let value = extra_args.next();
+        match (arg.as_str(), value) {
+            ("--bank", Some(value)) => match parse_bank_range(value) {
+                Some(range) => banks = range,
+                None => {
+                    eprintln!("Bad bank range: {}", value);
+                    process::exit(2);
+                }
+            },
+            ("--sym", Some(value)) => sym_path = value.into(),
+            ("--start", Some(value)) | ("--end", Some(value)) => match parse_number(value) {
+                Some(addr) if arg == "--start" => start = Some(addr),
+                Some(addr) => end = Some(addr),
+                None => {
+                    eprintln!("Bad address: {}", value);
+                    process::exit(2);
+                }
+            },
+            _ => {
+                eprintln!("Unknown option or missing value: {}", arg);
+                process::exit(2);
+            }
+        }
+    }
+
+    if banks.0 > banks.1 || banks.1 >= bank_count {
+        eprintln!("Bank range {}-{} is outside the rom's {} banks", banks.0, banks.1, bank_count);
+        process::exit(2);
+    }
+
+    let symbols = if sym_path.exists() {
+        match SymbolTable::load(&sym_path) {
+            Ok(symbols) => Some(symbols),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(2);
+            }
+        }
+    } else {
+        None
+    };
+
+    for bank in banks.0..=banks.1 {
+        let data = &rom[bank * BANK_SIZE..((bank + 1) * BANK_SIZE).min(rom.len())];
+        let base = bank_base_addr(bank);
+        let first = start.unwrap_or(base).clamp(base, base + data.len());
+        let last = end.unwrap_or(base + data.len() - 1).min(base + data.len() - 1);
+
+        println!("; bank {:#04x}", bank);
+        let mut addr = first;
+        while addr <= last {
+            let instr = disassemble(&data[addr - base..], addr as u16);
+            match &symbols {
+                Some(symbols) => {
+                    if let Some(label) = symbols.label_at(bank, addr as u16) {
+                        println!("{}:", label);
+                    }
+                    //code in bank 0 can only be matched to a switchable bank label when there is just one
+                    let romx_bank = if bank == 0 && bank_count <= 2 { 1 } else { bank };
+                    println!("{:02x}:{}", bank, symbols.annotate(&instr, romx_bank));
+                }
+                None => println!("{:02x}:{}", bank, instr),
+            }
+            addr += instr.length() as usize;
+        }
+    }
+}
@@ -0,0 +1,360 @@
+use legumegb_rs::{Gameboy, GameboyOptions, SerialBuffer, SystemType, GB_SCREEN_HEIGHT, GB_SCREEN_WIDTH};
+use std::{
+    env, fs,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    process,
+    time::Instant,
+};
+
+//runs blargg, mooneye and acid2 style test roms without a window and prints a pass/fail table.
+//every rom is checked for all of the completion signals at once:
+//  - blargg: "Passed"/"Failed" printed over serial, or the 0xde 0xb0 0x61 signature at 0xa001
+//    with the result code at 0xa000 and the message from 0xa004
+//  - mooneye: ld b,b with b/c/d/e/h/l holding 3/5/8/13/21/34 for a pass, all 0x42 for a fail
+//  - acid2: ld b,b with a reference image next to the rom, either <rom name>.png or
+//    reference-dmg.png, which the screen is compared against
+//ld b,b is also an ordinary instruction (blargg's ld r,r test runs it), so it only ends the
+//run for roms that haven't shown any blargg output, and only when the registers or the
+//reference image give a result. anything else keeps running
+
+const DEFAULT_TIMEOUT_SECONDS: u32 = 120;
+const FRAMES_PER_SECOND: u32 = 60;
+
+const OPCODE_LD_B_B: u8 = 0x40;
+const MOONEYE_PASS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const MOONEYE_FAIL: [u8; 6] = [0x42; 6];
+
+const BLARGG_STATUS_ADDR: u16 = 0xa000;
+const BLARGG_SIGNATURE_ADDR: u16 = 0xa001;
+const BLARGG_SIGNATURE: [u8; 3] = [0xde, 0xb0, 0x61];
+const BLARGG_TEXT_ADDR: u16 = 0xa004;
+const BLARGG_RUNNING: u8 = 0x80;
+
+const REFERENCE_IMAGE_NAME: &str = "reference-dmg.png";
+
+enum Outcome {
+    Pass,
+    Fail,
+    Timeout,
+    Error,
+}
+
+impl Outcome {
+    fn name(&self) -> &'static str {
+        match self {
+            Outcome::Pass => "PASS",
+            Outcome::Fail => "FAIL",
+            Outcome::Timeout => "TIMEOUT",
+            Outcome::Error => "ERROR",
+        }
+    }
+}
+
+struct TestResult {
+    name: String,
+    outcome: Outcome,
+    detail: String,
+    seconds: f64,
+}
+
+fn find_roms(dir: &Path, roms: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", dir.display(), e);
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            find_roms(&path, roms);
+        } else if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gb")) {
+            roms.push(path);
+        }
+    }
+}
+
+fn find_reference_image(rom_path: &Path) -> Option<PathBuf> {
+    let beside_rom = rom_path.with_extension("png");
+    if beside_rom.exists() {
+        return Some(beside_rom);
+    }
+    let shared = rom_path.with_file_name(REFERENCE_IMAGE_NAME);
+    if shared.exists() {
+        return Some(shared);
+    }
+    None
+}
+
+//reference pixels are reduced to the four dmg shades so the palette used to make the
+//image doesn't matter, only which shade each pixel is
+fn load_reference_shades(path: &Path) -> Result<Vec<u8>, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut buf = vec![0u8; reader.output_buffer_size().ok_or("image too large")?];
+    let info = reader.next_frame(&mut buf).map_err(|e| e.to_string())?;
+
+    if info.width as usize != GB_SCREEN_WIDTH || info.height as usize != GB_SCREEN_HEIGHT {
+        return Err(format!("reference image is {}x{}", info.width, info.height));
+    }
+
+    let channels = info.color_type.samples();
+    let mut shades = Vec::with_capacity(GB_SCREEN_WIDTH * GB_SCREEN_HEIGHT);
+    for y in 0..GB_SCREEN_HEIGHT {
+        let line = &buf[y * info.line_size..];
+        for x in 0..GB_SCREEN_WIDTH {
+            let pixel = &line[x * channels..];
+            let gray = if channels >= 3 {
+                (pixel[0] as u32 + pixel[1] as u32 + pixel[2] as u32) / 3
+            } else {
+                pixel[0] as u32
+            };
+            shades.push(shade_of(gray));
+        }
+    }
+    Ok(shades)
+}
+
+fn shade_of(gray: u32) -> u8 {
+    3 - ((gray + 0x2a) / 0x55).min(3) as u8
+}
+
+fn compare_screen(screen: &[u32], reference: &[u8]) -> usize {
+    if screen.len() != reference.len() {
+        return reference.len();
+    }
+    screen
+        .iter()
+        .zip(reference)
+        .filter(|(pixel, shade)| shade_of(**pixel & 0xff) != **shade)
+        .count()
+}
+
+fn read_blargg_text(gb: &mut Gameboy) -> String {
+    let mut text = String::new();
+    let mut addr = BLARGG_TEXT_ADDR;
+    while addr < 0xc000 {
+        let byte = gb.read_byte_raw(std::num::Wrapping(addr)).0;
+        if byte == 0 {
+            break;
+        }
+        text.push(byte as char);
+        addr += 1;
+    }
+    text
+}
+
+fn has_blargg_signature(gb: &mut Gameboy) -> bool {
+    BLARGG_SIGNATURE
+        .iter()
+        .enumerate()
+        .all(|(i, byte)| gb.read_byte_raw(std::num::Wrapping(BLARGG_SIGNATURE_ADDR + i as u16)).0 == *byte)
+}
+
+fn check_blargg_memory(gb: &mut Gameboy) -> Option<(Outcome, String)> {
+    if !has_blargg_signature(gb) {
+        return None;
+    }
+
+    let status = gb.read_byte_raw(std::num::Wrapping(BLARGG_STATUS_ADDR)).0;
+    if status == BLARGG_RUNNING {
+        return None;
+    }
+
+    let text = last_line(&read_blargg_text(gb));
+    if status == 0 {
+        Some((Outcome::Pass, text))
+    } else {
+        Some((Outcome::Fail, format!("result code {}: {}", status, text)))
+    }
+}
+
+fn check_blargg_serial(output: &str) -> Option<(Outcome, String)> {
+    if output.contains("Passed") {
+        return Some((Outcome::Pass, last_line(output)));
+    }
+    if output.contains("Failed") {
+        return Some((Outcome::Fail, last_line(output)));
+    }
+    None
+}
+
+fn last_line(text: &str) -> String {
+    text.lines().map(str::trim).rfind(|line| !line.is_empty()).unwrap_or("").to_string()
+}
+
+//none means the ld b,b wasn't a finish signal and the rom should keep running
+fn check_ld_b_b(gb: &Gameboy, reference: Option<&(PathBuf, Result<Vec<u8>, String>)>) -> Option<(Outcome, String)> {
+    if let Some((path, shades)) = reference {
+        return Some(match shades {
+            Ok(shades) => match compare_screen(gb.get_screen(), shades) {
+                0 => (Outcome::Pass, format!("screen matches {}", path.display())),
+                diff => (Outcome::Fail, format!("{} pixels differ from {}", diff, path.display())),
+            },
+            Err(e) => (Outcome::Error, format!("bad reference image {}: {}", path.display(), e)),
+        });
+    }
+
+    let reg = gb.get_registers();
+    let values = [reg.b.0, reg.c.0, reg.d.0, reg.e.0, reg.h.0, reg.l.0];
+    if values == MOONEYE_PASS {
+        Some((Outcome::Pass, String::from("fibonacci registers")))
+    } else if values == MOONEYE_FAIL {
+        Some((Outcome::Fail, String::from("failure registers")))
+    } else {
+        None
+    }
+}
+
+fn run_rom(path: &Path, bootrom: Option<&[u8]>, timeout_seconds: u32) -> (Outcome, String) {
+    let rom_data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) => return (Outcome::Error, e.to_string()),
+    };
+
+    let options = GameboyOptions { allow_unsupported_mapper: true };
+    let bootrom_data = bootrom.map(|b| b.to_vec()).unwrap_or_else(|| vec![0u8; 0x100]);
+    let mut gb = match Gameboy::from_bytes_with_options(SystemType::DMG, rom_data, bootrom_data, &options) {
+        Ok(gb) => gb,
+        Err(e) => return (Outcome::Error, e.to_string()),
+    };
+    if bootrom.is_none() {
+        gb.skip_bootrom();
+    }
+
+    let serial = SerialBuffer::new();
+    gb.set_link_endpoint(Box::new(serial.clone()));
+    let reference = find_reference_image(path).map(|p| {
+        let shades = load_reference_shades(&p);
+        (p, shades)
+    });
+
+    let mut serial_output = String::new();
+    let mut frames = 0;
+    while frames < timeout_seconds * FRAMES_PER_SECOND {
+        //the boot rom lives below 0x100, so only game code is checked
+        let pc = gb.get_pc();
+        if pc >= 0x100
+            && !gb.is_halted()
+            && gb.read_byte_raw(std::num::Wrapping(pc)).0 == OPCODE_LD_B_B
+            && serial_output.is_empty()
+            && !has_blargg_signature(&mut gb)
+        {
+            if let Some(result) = check_ld_b_b(&gb, reference.as_ref()) {
+                return result;
+            }
+        }
+
+        if let Some(lockup) = gb.get_lockup() {
+            return (Outcome::Fail, format!("cpu locked up on {}", lockup));
+        }
+
+        match legumegb_rs::step(&mut gb) {
+            Ok(Some(_)) => {
+                frames += 1;
+                serial_output.extend(serial.take_bytes().into_iter().map(|b| b as char));
+                if let Some(result) = check_blargg_serial(&serial_output) {
+                    return result;
+                }
+                if let Some(result) = check_blargg_memory(&mut gb) {
+                    return result;
+                }
+            }
+            Ok(None) => {}
+            Err(e) => return (Outcome::Fail, format!("cpu {} at {:#06x}", e, pc)),
+        }
+    }
+
+    let output = last_line(&serial_output);
+    (Outcome::Timeout, if output.is_empty() { format!("no result after {}s", timeout_seconds) } else { output })
+}
+
+fn print_table(results: &[TestResult]) {
+    let name_width = results.iter().map(|r| r.name.len()).max().unwrap_or(0).max(4);
+    println!("{:<name_width$}  {:<7}  {:>7}  DETAIL", "ROM", "RESULT", "TIME");
+    println!("{}", "-".repeat(name_width + 28));
+    for result in results {
+        println!(
+            "{:<name_width$}  {:<7}  {:>6.2}s  {}",
+            result.name,
+            result.outcome.name(),
+            result.seconds,
+            result.detail
+        );
+    }
+
+    let passed = results.iter().filter(|r| matches!(r.outcome, Outcome::Pass)).count();
+    println!("{}", "-".repeat(name_width + 28));
+    println!("{}/{} passed", passed, results.len());
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("Arguments: {} <rom directory> [--bootrom <bootrom file>] [--timeout <seconds>]", args[0]);
+        process::exit(2);
+    }
+
+    let mut bootrom = None;
+    let mut timeout_seconds = DEFAULT_TIMEOUT_SECONDS;
+    let mut extra_args = args[2..].iter();
+    while let Some(arg) = extra_args.next() {
+        match arg.as_str() {
+            "--bootrom" => match extra_args.next().map(fs::read) {
+                Some(Ok(data)) => bootrom = Some(data),
+                Some(Err(e)) => {
+                    eprintln!("Failed to read bootrom: {}", e);
+                    process::exit(2);
+                }
+                None => {
+                    eprintln!("--bootrom needs a bootrom file");
+                    process::exit(2);
+                }
+            },
+            "--timeout" => match extra_args.next().and_then(|v| v.parse().ok()) {
+                Some(seconds) => timeout_seconds = seconds,
+                None => {
+                    eprintln!("--timeout needs a number of emulated seconds");
+                    process::exit(2);
+                }
+            },
+            _ => {
+                eprintln!("Unknown option: {}", arg);
+                process::exit(2);
+            }
+        }
+    }
+
+    let rom_dir = Path::new(&args[1]);
+    let mut roms = Vec::new();
+    find_roms(rom_dir, &mut roms);
+    roms.sort();
+    if roms.is_empty() {
+        eprintln!("No .gb files found in {}", rom_dir.display());
+        process::exit(2);
+    }
+
+    let mut results = Vec::new();
+    for rom in &roms {
+        let name = rom.strip_prefix(rom_dir).unwrap_or(rom).display().to_string();
+        let start_time = Instant::now();
+        let (outcome, detail) = run_rom(rom, bootrom.as_deref(), timeout_seconds);
+        results.push(TestResult {
+            name,
+            outcome,
+            detail,
+            seconds: start_time.elapsed().as_secs_f64(),
+        });
+    }
+
+    print_table(&results);
+    if results.iter().any(|r| !matches!(r.outcome, Outcome::Pass)) {
+        process::exit(1);
+    }
+}
@@ -1,22 +1,53 @@
-use ppu::Color;
+use apu::{AudioSink, APU};
+use cart_header::CartHeader;
+use debugger::{BreakReason, Debugger};
 use core::num::Wrapping as W;
 use game_carts::GameCart;
 use banked_memory::BankedMemory;
-use log::{debug, error, info};
-use minifb::Key;
+use log::{error, info, warn};
 use ppu::PPU;
+use profiler::Profiler;
 use registers::Registers;
+use rewind::RewindBuffer;
+use serial::{LinkEndpoint, Serial};
+use symbols::SymbolTable;
+use trace::TraceLogger;
 
-use std::{fs, fs::File, io::BufWriter, io::Write};
+use std::fs;
+use std::path::{Path, PathBuf};
 
-use self::render::InputKey;
+use self::error::GameboyError;
+use self::input::InputKey;
+use self::save_state::{StateReader, StateWriter, SAVE_STATE_MAGIC, SAVE_STATE_VERSION};
 
+pub mod apu;
 pub mod banked_memory;
+pub mod cart_header;
+pub mod debugger;
+pub mod disassembler;
+pub mod error;
+#[cfg(feature = "singlestep")]
+pub mod flat_bus;
 pub mod game_carts;
+pub mod gdb_stub;
+pub mod input;
 pub mod io_reg;
+pub mod link_cable;
+pub mod local_link;
 pub mod opcodes;
 pub mod ppu;
+pub mod printer;
+pub mod profiler;
 pub mod registers;
+pub mod rewind;
+pub mod rtc;
+pub mod save_state;
+pub mod serial;
+pub mod symbols;
+pub mod timer;
+pub mod trace;
+pub mod wav_sink;
+#[cfg(feature = "frontend")]
 pub mod render;
 
 #[derive(Debug)]
@@ -50,11 +81,47 @@ const INT_TIMER: u8 = 0x4;
 const INT_SERIAL: u8 = 0x8;
 const INT_GAMEPAD: u8 = 0x10;
 
+//io registers the dmg boot rom leaves set up, apu power goes first so the rest stick
+const POST_BOOT_IO: [(u16, u8); 9] = [
+    (0xff26, 0xf1),
+    (0xff25, 0xf3),
+    (0xff24, 0x77),
+    (0xff11, 0x80),
+    (0xff12, 0xf3),
+    (0xff40, 0x91),
+    (0xff47, 0xfc),
+    (0xff48, 0xff),
+    (0xff49, 0xff),
+];
+
+//the cpu hangs for good on an undefined opcode, the rest of the hardware keeps running
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CpuLockup {
+    pub opcode: u8,
+    pub pc: u16,
+}
+
+impl std::fmt::Display for CpuLockup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "illegal opcode {:#04x} at {:#06x}", self.opcode, self.pc)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GameboyOptions {
+    //boot carts with an unknown mapper as MBC1 instead of refusing to load them
+    pub allow_unsupported_mapper: bool,
+}
+
 pub struct Gameboy {
     reg: Registers,
     ppu: PPU,
+    apu: APU,
+    serial: Serial,
     bootrom_data: Vec<u8>,
     rom: Box<dyn GameCart>,
+    cart_header: CartHeader,
+    battery_save_path: Option<PathBuf>,
     wram: BankedMemory,
     hram: Vec<u8>,
     pc: W<u16>,
@@ -63,6 +130,16 @@ pub struct Gameboy {
     cycles_pending: u32,
     cycles_run: u128,
     display_frame_cycles: i32,
+    rewind: Option<RewindBuffer>,
+    //when set every access goes to this 64k array instead of the memory map
+    #[cfg(feature = "singlestep")]
+    flat_bus: Option<flat_bus::FlatBus>,
+    debugger: Option<Debugger>,
+    symbols: Option<SymbolTable>,
+    trace: Option<TraceLogger>,
+    profiler: Option<Profiler>,
+    //LY reads as 0x90, see trace::TraceOptions
+    doctor_ly: bool,
     other_state: OtherState,
 }
 
@@ -72,11 +149,19 @@ pub struct OtherState {
     int_enable: u8,
     int_flag: u8,
     halted: bool,
+    //next fetch doesn't advance pc, set by halt with ime off and an interrupt pending
+    halt_bug: bool,
+    //ime was turned on by an ei right before this instruction
+    ime_just_enabled: bool,
+    stopped: bool,
+    lockup: Option<CpuLockup>,
     instrs_run: u128,
     counter_div: W<u16>,
     counter_tima: W<u8>,
     counter_tma: u8,
     counter_tac: u8,
+    tima_reload_pending: bool,
+    tima_reloading: bool,
     joypad_io_state: u8,
     oam_dma_running: bool,
     oam_dma_start_addr: u16,
@@ -85,6 +170,12 @@ pub struct OtherState {
     force_crash: bool,
 }
 
+impl Default for OtherState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl OtherState {
     pub fn new() -> OtherState {
         OtherState {
@@ -93,19 +184,79 @@ impl OtherState {
             int_enable: 0,
             int_flag: 0,
             halted: false,
+            halt_bug: false,
+            ime_just_enabled: false,
+            stopped: false,
+            lockup: None,
             instrs_run: 0,
             counter_div: W(0),
             counter_tima: W(0),
             counter_tma: 0,
             counter_tac: 0,
+            tima_reload_pending: false,
+            tima_reloading: false,
             joypad_io_state: 0,
             oam_dma_running: false,
             oam_dma_start_addr: 0,
             oam_dma_cur_addr: 0,
-            input_keys: Vec::<InputKey>::with_capacity(8),
+            input_keys: input::new_joypad_keys(),
             force_crash: false,
         }
     }
+
+    //input keys are owned by the frontend and are left alone
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.bootrom_enabled);
+        w.write_bool(self.ime_next_cycle);
+        w.write_u8(self.int_enable);
+        w.write_u8(self.int_flag);
+        w.write_bool(self.halted);
+        w.write_bool(self.halt_bug);
+        w.write_bool(self.stopped);
+        w.write_bool(self.lockup.is_some());
+        if let Some(lockup) = self.lockup {
+            w.write_u8(lockup.opcode);
+            w.write_u16(lockup.pc);
+        }
+        w.write_u128(self.instrs_run);
+        w.write_u16(self.counter_div.0);
+        w.write_u8(self.counter_tima.0);
+        w.write_u8(self.counter_tma);
+        w.write_u8(self.counter_tac);
+        w.write_bool(self.tima_reload_pending);
+        w.write_bool(self.tima_reloading);
+        w.write_u8(self.joypad_io_state);
+        w.write_bool(self.oam_dma_running);
+        w.write_u16(self.oam_dma_start_addr);
+        w.write_u8(self.oam_dma_cur_addr);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), GameboyError> {
+        self.bootrom_enabled = r.read_bool()?;
+        self.ime_next_cycle = r.read_bool()?;
+        self.int_enable = r.read_u8()?;
+        self.int_flag = r.read_u8()?;
+        self.halted = r.read_bool()?;
+        self.halt_bug = r.read_bool()?;
+        self.stopped = r.read_bool()?;
+        self.lockup = if r.read_bool()? {
+            Some(CpuLockup { opcode: r.read_u8()?, pc: r.read_u16()? })
+        } else {
+            None
+        };
+        self.instrs_run = r.read_u128()?;
+        self.counter_div = W(r.read_u16()?);
+        self.counter_tima = W(r.read_u8()?);
+        self.counter_tma = r.read_u8()?;
+        self.counter_tac = r.read_u8()?;
+        self.tima_reload_pending = r.read_bool()?;
+        self.tima_reloading = r.read_bool()?;
+        self.joypad_io_state = r.read_u8()?;
+        self.oam_dma_running = r.read_bool()?;
+        self.oam_dma_start_addr = r.read_u16()?;
+        self.oam_dma_cur_addr = r.read_u8()?;
+        Ok(())
+    }
 }
 
 impl Gameboy {
@@ -113,16 +264,84 @@ impl Gameboy {
         system_type: SystemType,
         rom_file_path: &str,
         bootrom_file_path: &str
-    ) -> Gameboy {
-        let rom_data = fs::read(rom_file_path).unwrap();
-        let bootrom_data = fs::read(bootrom_file_path).unwrap();
+    ) -> Result<Gameboy, GameboyError> {
+        Gameboy::new_with_options(system_type, rom_file_path, bootrom_file_path, &GameboyOptions::default())
+    }
+
+    pub fn new_with_options(
+        system_type: SystemType,
+        rom_file_path: &str,
+        bootrom_file_path: &str,
+        options: &GameboyOptions
+    ) -> Result<Gameboy, GameboyError> {
+        let rom_data = read_file(rom_file_path)?;
+        let bootrom_data = read_file(bootrom_file_path)?;
+
+        let mut gb = Gameboy::from_bytes_with_options(system_type, rom_data, bootrom_data, options)?;
+        if gb.has_battery() {
+            gb.set_battery_save_path(Path::new(rom_file_path).with_extension("sav"))?;
+        }
+        //a bad symbol file shouldn't stop the game from booting
+        let sym_path = Path::new(rom_file_path).with_extension("sym");
+        if sym_path.exists() {
+            if let Err(e) = gb.load_symbols(&sym_path) {
+                warn!("Failed to load symbols: {}", e);
+            }
+        }
+        Ok(gb)
+    }
+
+    pub fn from_bytes(
+        system_type: SystemType,
+        rom_data: impl Into<Vec<u8>>,
+        bootrom_data: impl Into<Vec<u8>>
+    ) -> Result<Gameboy, GameboyError> {
+        Gameboy::from_bytes_with_options(system_type, rom_data, bootrom_data, &GameboyOptions::default())
+    }
+
+    pub fn from_bytes_with_options(
+        system_type: SystemType,
+        rom_data: impl Into<Vec<u8>>,
+        bootrom_data: impl Into<Vec<u8>>,
+        options: &GameboyOptions
+    ) -> Result<Gameboy, GameboyError> {
+        let rom_data = rom_data.into();
+        let bootrom_data = bootrom_data.into();
+
+        if bootrom_data.len() != BOOTROM_SIZE as usize {
+            return Err(GameboyError::BadBootromSize { size: bootrom_data.len() });
+        }
+
+        let cart_header = CartHeader::parse(&rom_data)?;
+        info!("Cartridge header:\n{}", cart_header);
+        if !cart_header.header_checksum_valid() {
+            warn!("Cartridge header checksum mismatch, a real DMG would refuse to boot this ROM");
+        }
+        if let Some(size) = cart_header.rom_size() {
+            if size != rom_data.len() {
+                warn!("ROM size in header ({:#x}) does not match file size ({:#x})", size, rom_data.len());
+            }
+        }
+        let rom = game_carts::get_cart(rom_data, &cart_header, options.allow_unsupported_mapper)?;
+        Ok(Gameboy::with_cart(system_type, rom, cart_header, bootrom_data))
+    }
 
+    fn with_cart(
+        system_type: SystemType,
+        rom: Box<dyn GameCart>,
+        cart_header: CartHeader,
+        bootrom_data: Vec<u8>
+    ) -> Gameboy {
         match system_type {
             SystemType::DMG => {
-                let mut dmg_ret = Gameboy {
+                Gameboy {
                     reg: Registers::new(),
                     ppu: PPU::new(system_type),
-                    rom: game_carts::get_cart(rom_data),
+                    apu: APU::new(),
+                    serial: Serial::new(),
+                    rom,
+                    cart_header,
+                    battery_save_path: None,
                     bootrom_data,
                     wram: BankedMemory::new_empty(false, 1, 0x2000, true, String::from("dmg wram")),
                     hram: vec![0u8; 0x80],
@@ -132,40 +351,290 @@ impl Gameboy {
                     cycles_pending: 0,
                     cycles_run: 0,
                     display_frame_cycles: 0,
+                    rewind: None,
+                    #[cfg(feature = "singlestep")]
+                    flat_bus: None,
+                    debugger: None,
+                    symbols: None,
+                    trace: None,
+                    profiler: None,
+                    doctor_ly: false,
                     other_state: OtherState::new(),
-                };
-
-                dmg_ret
-                    .other_state
-                    .input_keys
-                    .push(InputKey::new(Key::Enter)); //start
-                dmg_ret
-                    .other_state
-                    .input_keys
-                    .push(InputKey::new(Key::Space)); //sel
-                dmg_ret.other_state.input_keys.push(InputKey::new(Key::S)); //b
-                dmg_ret.other_state.input_keys.push(InputKey::new(Key::A)); //a
-                dmg_ret
-                    .other_state
-                    .input_keys
-                    .push(InputKey::new(Key::Down)); //down
-                dmg_ret.other_state.input_keys.push(InputKey::new(Key::Up)); //up
-                dmg_ret
-                    .other_state
-                    .input_keys
-                    .push(InputKey::new(Key::Left)); //left
-                dmg_ret
-                    .other_state
-                    .input_keys
-                    .push(InputKey::new(Key::Right)); //right
-                return dmg_ret;
+                }
+            }
+        }
+    }
+
+    pub fn get_screen(&self) -> &[u32] {
+        self.ppu.get_screen()
+    }
+
+    pub fn get_cart_header(&self) -> &CartHeader {
+        &self.cart_header
+    }
+
+    pub fn get_registers(&self) -> &Registers {
+        &self.reg
+    }
+
+    pub fn get_registers_mut(&mut self) -> &mut Registers {
+        &mut self.reg
+    }
+
+    pub fn get_pc(&self) -> u16 {
+        self.pc.0
+    }
+
+    pub fn set_pc(&mut self, value: u16) {
+        self.pc = W(value);
+    }
+
+    pub fn get_sp(&self) -> u16 {
+        self.sp.0
+    }
+
+    pub fn set_sp(&mut self, value: u16) {
+        self.sp = W(value);
+    }
+
+    pub fn get_ime(&self) -> bool {
+        self.ime
+    }
+
+    pub fn set_ime(&mut self, value: bool) {
+        self.ime = value;
+        self.other_state.ime_next_cycle = false;
+    }
+
+    //cycles the last step took, including the opcode fetch
+    pub fn get_last_step_cycles(&self) -> u32 {
+        self.cycles_pending
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.other_state.halted
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.other_state.stopped
+    }
+
+    //set once the cpu has hit an undefined opcode, only a reset or loading a state clears it
+    pub fn get_lockup(&self) -> Option<CpuLockup> {
+        self.other_state.lockup
+    }
+
+    //puts the cpu and io registers where the dmg boot rom leaves them, for running a rom
+    //without a boot rom image
+    pub fn skip_bootrom(&mut self) {
+        self.reg.set_af(W(0x01b0));
+        self.reg.set_bc(W(0x0013));
+        self.reg.set_de(W(0x00d8));
+        self.reg.set_hl(W(0x014d));
+        self.sp = W(0xfffe);
+        self.pc = W(0x0100);
+        self.other_state.bootrom_enabled = false;
+        self.other_state.counter_div = W(0xabcc);
+        self.other_state.int_flag = INT_VBLANK;
+
+        for (addr, value) in POST_BOOT_IO {
+            self.write_byte_raw(W(addr), W(value));
+        }
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.rom.save_battery().is_some()
+    }
+
+    //sets where battery ram is flushed to, loading it first if the file already exists
+    pub fn set_battery_save_path(&mut self, path: PathBuf) -> Result<(), GameboyError> {
+        if path.exists() {
+            let data = read_file(&path.to_string_lossy())?;
+            self.rom.load_battery(&data);
+            self.rom.clear_battery_dirty();
+            info!("Loaded battery save from {}", path.display());
+        }
+        self.battery_save_path = Some(path);
+        Ok(())
+    }
+
+    pub fn flush_battery(&mut self) -> Result<(), GameboyError> {
+        if !self.rom.battery_dirty() {
+            return Ok(());
+        }
+
+        if let (Some(path), Some(data)) = (&self.battery_save_path, self.rom.save_battery()) {
+            fs::write(path, data).map_err(|source| GameboyError::FileWrite {
+                path: path.to_string_lossy().into_owned(),
+                source,
+            })?;
+            self.rom.clear_battery_dirty();
+        }
+        Ok(())
+    }
+
+    pub fn set_rtc_clock(&mut self, clock: Box<dyn rtc::RtcClock>) {
+        self.rom.set_rtc_clock(clock);
+    }
+
+    pub fn is_rumble_active(&self) -> bool {
+        self.rom.rumble_active()
+    }
+
+    pub fn set_audio_sink(&mut self, sink: Option<Box<dyn AudioSink>>) {
+        self.apu.set_sink(sink);
+    }
+
+    pub fn set_audio_sample_rate(&mut self, sample_rate: u32) {
+        self.apu.set_sample_rate(sample_rate);
+    }
+
+    pub fn set_link_endpoint(&mut self, link: Box<dyn LinkEndpoint>) {
+        self.serial.set_link(link);
+    }
+
+    //header is the magic, format version, then the cart type and global checksum so a
+    //state can't be loaded into a different game
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        for byte in SAVE_STATE_MAGIC {
+            w.write_u8(byte);
+        }
+        w.write_u32(SAVE_STATE_VERSION);
+        w.write_u8(self.cart_header.cart_type);
+        w.write_u16(self.cart_header.computed_global_checksum);
+
+        self.save_state_body(&mut w);
+        w.into_bytes()
+    }
+
+    //on failure the emulator is left exactly as it was before the call
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), GameboyError> {
+        let mut r = StateReader::new(data);
+        let mut magic = [0u8; 4];
+        for byte in magic.iter_mut() {
+            *byte = r.read_u8()?;
+        }
+        if magic != SAVE_STATE_MAGIC {
+            return Err(GameboyError::BadSaveState { reason: String::from("not a save state") });
+        }
+
+        let version = r.read_u32()?;
+        if version != SAVE_STATE_VERSION {
+            return Err(GameboyError::SaveStateVersion { version });
+        }
+
+        let cart_type = r.read_u8()?;
+        let global_checksum = r.read_u16()?;
+        if cart_type != self.cart_header.cart_type || global_checksum != self.cart_header.computed_global_checksum {
+            return Err(GameboyError::BadSaveState { reason: String::from("state was made with a different rom") });
+        }
+
+        let mut backup = StateWriter::new();
+        self.save_state_body(&mut backup);
+
+        let result = self.load_state_body(&mut r).and_then(|_| {
+            if !r.is_finished() {
+                return Err(GameboyError::BadSaveState { reason: String::from("trailing data") });
+            }
+            Ok(())
+        });
+        if result.is_err() {
+            let backup = backup.into_bytes();
+            self.load_state_body(&mut StateReader::new(&backup))
+                .expect("restoring the pre-load state can't fail");
+        }
+        result
+    }
+
+    pub fn save_state_to_file(&self, path: &Path) -> Result<(), GameboyError> {
+        fs::write(path, self.save_state()).map_err(|source| GameboyError::FileWrite {
+            path: path.to_string_lossy().into_owned(),
+            source,
+        })
+    }
+
+    pub fn load_state_from_file(&mut self, path: &Path) -> Result<(), GameboyError> {
+        let data = read_file(&path.to_string_lossy())?;
+        self.load_state(&data)
+    }
+
+    //keeps the last `seconds` of play, snapshotting every `frame_interval` frames in run_frame
+    pub fn enable_rewind(&mut self, seconds: usize, frame_interval: u32) {
+        self.rewind = Some(RewindBuffer::with_seconds(seconds, frame_interval));
+    }
+
+    pub fn disable_rewind(&mut self) {
+        self.rewind = None;
+    }
+
+    pub fn get_rewind_buffer(&self) -> Option<&RewindBuffer> {
+        self.rewind.as_ref()
+    }
+
+    //steps back one snapshot, returns false when there is no more history
+    pub fn rewind_step(&mut self) -> bool {
+        let state = match self.rewind.as_mut().and_then(|r| r.pop()) {
+            Some(state) => state,
+            None => return false,
+        };
+
+        if let Err(e) = self.load_state(&state) {
+            error!("Failed to restore rewind snapshot: {}", e);
+            if let Some(rewind) = &mut self.rewind {
+                rewind.clear();
             }
+            return false;
         }
+        true
+    }
+
+    fn save_state_body(&self, w: &mut StateWriter) {
+        self.reg.save_state(w);
+        w.write_u16(self.pc.0);
+        w.write_u16(self.sp.0);
+        w.write_bool(self.ime);
+        w.write_u32(self.cycles_pending);
+        w.write_u128(self.cycles_run);
+        w.write_i32(self.display_frame_cycles);
+        self.other_state.save_state(w);
+
+        self.wram.save_state(w);
+        w.write_bytes(&self.hram);
+        self.ppu.save_state(w);
+        self.apu.save_state(w);
+        self.serial.save_state(w);
+        self.rom.save_state(w);
+    }
+
+    fn load_state_body(&mut self, r: &mut StateReader) -> Result<(), GameboyError> {
+        self.reg.load_state(r)?;
+        self.pc = W(r.read_u16()?);
+        self.sp = W(r.read_u16()?);
+        self.ime = r.read_bool()?;
+        self.cycles_pending = r.read_u32()?;
+        self.cycles_run = r.read_u128()?;
+        self.display_frame_cycles = r.read_i32()?;
+        self.other_state.load_state(r)?;
+
+        self.wram.load_state(r)?;
+        r.read_bytes_into(&mut self.hram, "hram")?;
+        self.ppu.load_state(r)?;
+        self.apu.load_state(r)?;
+        self.serial.load_state(r)?;
+        self.rom.load_state(r)
     }
 
     pub fn debug(&self, offset_pc: bool) {
-        info!("AF: {:#06x}  BC: {:#06x}\nDE: {:#06x}  HL: {:#06x}\nSP: {:#06x}  PC: {:#06x}\nZ: {}  N: {}  H: {}  C: {}", 
-        self.reg.get_af(), self.reg.get_bc(), self.reg.get_de(), self.reg.get_hl(), self.sp, if offset_pc { self.pc - W(1) } else { self.pc },
+        let pc = if offset_pc { self.pc - W(1) } else { self.pc };
+        let bank = self.rom.rom_bank(pc.0);
+        let location = match self.symbolize(pc.0) {
+            Some(label) => format!(" ({:02x}:{})", bank, label),
+            None if pc.0 <= ROM_END => format!(" (bank {:02x})", bank),
+            None => String::new(),
+        };
+        info!("AF: {:#06x}  BC: {:#06x}\nDE: {:#06x}  HL: {:#06x}\nSP: {:#06x}  PC: {:#06x}{}\nZ: {}  N: {}  H: {}  C: {}", 
+        self.reg.get_af(), self.reg.get_bc(), self.reg.get_de(), self.reg.get_hl(), self.sp, pc, location,
         self.reg.get_flag_z() as i32, self.reg.get_flag_n() as i32, self.reg.get_flag_h() as i32, self.reg.get_flag_c() as i32);
     }
 
@@ -173,52 +642,70 @@ impl Gameboy {
     pub fn read_byte_raw(&mut self, addr: W<u16>) -> W<u8> {
         let addr = addr.0;
 
+        #[cfg(feature = "singlestep")]
+        if let Some(bus) = &self.flat_bus {
+            return W(bus.read(addr));
+        }
+
         match addr {
             ROM_START..=ROM_END => {
                 if self.other_state.bootrom_enabled && addr < BOOTROM_SIZE {
                     return W(self.bootrom_data[addr as usize]);
                 }
-                return W(self.rom.read_byte(addr));
+                W(self.rom.read_byte(addr))
             }
             VRAM_START..=VRAM_END => {
-                return W(self.ppu.read_vram_byte(addr - VRAM_START));
+                W(self.ppu.read_vram_byte(addr - VRAM_START))
             }
             CART_RAM_START..=CART_RAM_END => {
-                return W(self.rom.read_byte(addr));
+                W(self.rom.read_byte(addr))
             }
             WRAM_START..=WRAM_END => {
-                return W(self.wram.read_byte(addr - WRAM_START));
+                W(self.wram.read_byte(addr - WRAM_START))
             }
             ECHO_WRAM_START..=ECHO_WRAM_END => {
-                return W(self.wram.read_byte(addr - ECHO_WRAM_START));
+                W(self.wram.read_byte(addr - ECHO_WRAM_START))
             }
             OAM_START..=OAM_END => {
-                return W(self.ppu.read_oam_byte(addr - OAM_START));
+                W(self.ppu.read_oam_byte(addr - OAM_START))
             }
             IO_REG_START..=IO_REG_END => {
-                return W(io_reg::read_byte(self, addr as u8));
+                W(io_reg::read_byte(self, addr as u8))
             }
             HRAM_START..=HRAM_END => {
-                return W(self.hram[(addr - HRAM_START) as usize]);
+                W(self.hram[(addr - HRAM_START) as usize])
             }
             IE_ADDRESS => {
-                return W(self.other_state.int_enable);
+                W(self.other_state.int_enable)
             }
             _ => {
-                return W(UNDEFINED_READ);
+                W(UNDEFINED_READ)
             }
         }
     }
 
+    //timed read that doesn't trigger watchpoints, used for opcode and operand fetches
     #[inline(always)]
-    pub fn read_byte(&mut self, addr: W<u16>) -> W<u8> {
+    fn fetch_byte(&mut self, addr: W<u16>) -> W<u8> {
         self.cycles_pending += 4;
 
         if self.other_state.oam_dma_running && addr.0 < IO_REG_START {
             return W(UNDEFINED_READ);
         }
 
-        return self.read_byte_raw(addr);
+        let value = self.read_byte_raw(addr);
+        #[cfg(feature = "singlestep")]
+        flat_bus::log_access(self, addr.0, value.0, false);
+        value
+    }
+
+    #[inline(always)]
+    pub fn read_byte(&mut self, addr: W<u16>) -> W<u8> {
+        let value = self.fetch_byte(addr);
+        if let Some(dbg) = &mut self.debugger {
+            dbg.on_access(addr.0, value.0, false);
+        }
+        value
     }
 
     #[inline(always)]
@@ -226,6 +713,12 @@ impl Gameboy {
         let value = value.0;
         let addr = addr.0;
 
+        #[cfg(feature = "singlestep")]
+        if let Some(bus) = &mut self.flat_bus {
+            bus.write(addr, value);
+            return;
+        }
+
         match addr {
             ROM_START..=ROM_END => {
                 self.rom.write_byte(addr, value);
@@ -254,9 +747,7 @@ impl Gameboy {
             IE_ADDRESS => {
                 self.other_state.int_enable = value;
             }
-            _ => {
-                return;
-            }
+            _ => {}
         }
     }
 
@@ -264,16 +755,22 @@ impl Gameboy {
     pub fn write_byte(&mut self, addr: W<u16>, value: W<u8>) {
         self.cycles_pending += 4;
 
+        if let Some(dbg) = &mut self.debugger {
+            dbg.on_access(addr.0, value.0, true);
+        }
+
         if self.other_state.oam_dma_running && addr.0 < IO_REG_START {
             return;
         }
 
+        #[cfg(feature = "singlestep")]
+        flat_bus::log_access(self, addr.0, value.0, true);
         self.write_byte_raw(addr, value);
     }
 
     #[inline(always)]
     pub fn read_byte_inc_pc(&mut self) -> W<u8> {
-        let value = self.read_byte(self.pc);
+        let value = self.fetch_byte(self.pc);
         self.pc += 1;
         value
     }
@@ -310,105 +807,206 @@ impl Gameboy {
     }
 }
 
+fn read_file(path: &str) -> Result<Vec<u8>, GameboyError> {
+    fs::read(path).map_err(|source| GameboyError::FileRead {
+        path: String::from(path),
+        source,
+    })
+}
+
 pub fn run_frame<'a>(
     gb: &'a mut Gameboy,
-    input_keys: &Vec<InputKey>,
+    input_keys: &[InputKey],
 ) -> Result<Vec<u32>, &'a str> {
     handle_input(gb, input_keys);
     gb.display_frame_cycles = 70224;
     loop {
-        gb.other_state.instrs_run += 1;
-        gb.cycles_pending = 0;
+        //hand back the screen as it is so the frontend keeps drawing while paused
+        if gb.is_paused() {
+            return Ok(gb.get_screen().to_vec());
+        }
+        if let Some(frame) = step(gb)? {
+            push_rewind_snapshot(gb);
+            return Ok(frame);
+        }
+    }
+}
 
-        let mut opcode: W<u8> = W(0);
+fn push_rewind_snapshot(gb: &mut Gameboy) {
+    if gb.rewind.as_mut().is_some_and(|r| r.frame_tick()) {
+        let state = gb.save_state();
+        gb.rewind.as_mut().unwrap().push(state);
+    }
+}
 
-        if !gb.other_state.halted {
-            opcode = gb.read_byte_inc_pc();
-        } else {
-            gb.cycles_pending += 4;
-        }
+pub fn step(gb: &mut Gameboy) -> Result<Option<Vec<u32>>, &'static str> {
+    gb.other_state.instrs_run += 1;
+    gb.cycles_pending = 0;
 
-        if gb.other_state.ime_next_cycle {
-            gb.other_state.ime_next_cycle = false;
-            gb.ime = true;
-        }
+    if gb.other_state.stopped {
+        return Ok(step_stopped(gb));
+    }
 
-        if opcodes::run_opcode(gb, opcode) && !gb.other_state.force_crash {
-            gb.cycles_run += gb.cycles_pending as u128;
+    if debugger::before_step(gb) {
+        return Ok(None);
+    }
 
-            process_interrupts(gb);
+    let mut opcode: W<u8> = W(0);
+    let idle = gb.other_state.halted || gb.other_state.lockup.is_some();
+    if !idle && gb.trace.is_some() {
+        trace::log_instruction(gb);
+    }
+    if !idle {
+        profiler::start_instruction(gb);
+    }
 
-            if !gb.ppu.is_enabled() {
-                gb.display_frame_cycles -= gb.cycles_pending as i32;
-                if gb.display_frame_cycles <= 0 {
-                    gb.display_frame_cycles = 70224;
-                    return Ok(vec![ppu::Color::White as u32]);
-                }
-            }
+    if idle {
+        gb.cycles_pending += 4;
+    } else if gb.other_state.halt_bug {
+        gb.other_state.halt_bug = false;
+        opcode = gb.fetch_byte(gb.pc);
+    } else {
+        opcode = gb.read_byte_inc_pc();
+    }
 
-            if let Some(frame) = gb.ppu.run_cycles(gb.cycles_pending, &mut gb.other_state) {
-                return Ok(frame);
-            }
+    gb.other_state.ime_just_enabled = false;
+    if gb.other_state.ime_next_cycle {
+        gb.other_state.ime_next_cycle = false;
+        gb.other_state.ime_just_enabled = true;
+        gb.ime = true;
+    }
 
-            process_timers(gb);
+    if !opcodes::run_opcode(gb, opcode) {
+        let lockup = CpuLockup { opcode: opcode.0, pc: (gb.pc - W(1)).0 };
+        error!("CPU locked up on {}", lockup);
+        gb.debug(true);
+        error!("Ran for {} cycles.", gb.cycles_run);
+        gb.other_state.lockup = Some(lockup);
+        if let Some(dbg) = &mut gb.debugger {
+            dbg.set_break(BreakReason::Lockup(lockup));
+        }
+    } else if !idle {
+        debugger::after_step(gb, opcode.0);
+    }
 
-            if gb.other_state.oam_dma_running {
-                process_oam_dma(gb);
+    if !gb.other_state.force_crash {
+        if idle {
+            profiler::on_halted(gb, gb.cycles_pending);
+        } else {
+            profiler::finish_instruction(gb, gb.cycles_pending);
+        }
+
+        process_interrupts(gb);
+        gb.cycles_run += gb.cycles_pending as u128;
+
+        let mut frame = None;
+        if !gb.ppu.is_enabled() {
+            gb.display_frame_cycles -= gb.cycles_pending as i32;
+            if gb.display_frame_cycles <= 0 {
+                gb.display_frame_cycles = 70224;
+                frame = Some(vec![ppu::Color::White as u32]);
             }
+        }
 
-            //process serial, etc
-        } else {
-            error!("Invalid opcode {:#04x}!", opcode);
-            gb.debug(true);
-            error!("Ran for {} cycles.", gb.cycles_run);
-            return Err("crashed");
+        if let Some(ppu_frame) = gb.ppu.run_cycles(gb.cycles_pending, &mut gb.other_state) {
+            frame = Some(ppu_frame);
+        }
+
+        process_timers(gb);
+        gb.apu.run_cycles(gb.cycles_pending, gb.other_state.counter_div.0);
+
+        if gb.other_state.oam_dma_running {
+            process_oam_dma(gb);
         }
+
+        gb.serial.run_cycles(gb.cycles_pending, &mut gb.other_state);
+
+        Ok(frame)
+    } else {
+        error!("Forced crash at opcode {:#04x}!", opcode);
+        gb.debug(true);
+        error!("Ran for {} cycles.", gb.cycles_run);
+        Err("crashed")
+    }
+}
+
+//the system clock is stopped so nothing ticks, frames of the frozen screen keep coming at
+//the normal rate so the frontend stays responsive and keeps feeding in buttons
+fn step_stopped(gb: &mut Gameboy) -> Option<Vec<u32>> {
+    gb.cycles_pending = 4;
+    gb.cycles_run += 4;
+    gb.display_frame_cycles -= 4;
+    if gb.display_frame_cycles <= 0 {
+        gb.display_frame_cycles = 70224;
+        return Some(gb.get_screen().to_vec());
     }
+    None
 }
 
 pub fn process_interrupts(gb: &mut Gameboy) {
+    //a locked up cpu never takes an interrupt
+    if gb.other_state.lockup.is_some() {
+        return;
+    }
+
     let interrupts_to_process = gb.other_state.int_enable & gb.other_state.int_flag;
+    if (interrupts_to_process & 0x1f) == 0 {
+        return;
+    }
 
-    if (interrupts_to_process & 0x1f) != 0 {
-        gb.other_state.halted = false;
-
-        let interrupt_jump_addr: Option<W<u16>>;
-        let interrupt_mask: u8;
-        if (interrupts_to_process & INT_VBLANK) != 0 {
-            interrupt_jump_addr = Some(W(0x40));
-            interrupt_mask = !INT_VBLANK;
-        } else if (interrupts_to_process & INT_STAT) != 0 {
-            interrupt_jump_addr = Some(W(0x48));
-            interrupt_mask = !INT_STAT;
-        } else if (interrupts_to_process & INT_TIMER) != 0 {
-            interrupt_jump_addr = Some(W(0x50));
-            interrupt_mask = !INT_TIMER;
-        } else if (interrupts_to_process & INT_SERIAL) != 0 {
-            interrupt_jump_addr = Some(W(0x58));
-            interrupt_mask = !INT_SERIAL;
-        } else if (interrupts_to_process & INT_GAMEPAD) != 0 {
-            interrupt_jump_addr = Some(W(0x60));
-            interrupt_mask = !INT_GAMEPAD;
-        } else {
-            interrupt_jump_addr = None;
-            interrupt_mask = 0xff;
-        }
+    //any pending interrupt ends halt, even with ime off
+    let was_halted = gb.other_state.halted;
+    gb.other_state.halted = false;
+    if !gb.ime {
+        return;
+    }
+
+    let dispatch_start = gb.cycles_pending;
+    //leaving halt costs one more m-cycle before the dispatch starts
+    if was_halted {
+        gb.cycles_pending += 4;
+    }
 
-        if gb.ime && interrupt_jump_addr.is_some() {
-            gb.cycles_pending += 8;
-            gb.push_short(gb.pc);
-            gb.cycles_pending += 4;
-            gb.ime = false;
-            gb.other_state.int_flag &= interrupt_mask;
+    //dispatch takes 5 m-cycles, two idle, two pushes, one to jump. the vector is only
+    //picked after the high byte of pc is pushed, so if that push lands on ie and clears the
+    //pending bit the dispatch is cancelled and jumps to 0x0000 instead
+    gb.ime = false;
+    gb.cycles_pending += 8;
+    gb.sp -= 1;
+    gb.write_byte(gb.sp, W((gb.pc.0 >> 8) as u8));
 
-            let interrupt_jump_addr = interrupt_jump_addr.unwrap();
-            gb.pc = interrupt_jump_addr;
-        }
+    let interrupts_to_process = gb.other_state.int_enable & gb.other_state.int_flag;
+    let (interrupt_jump_addr, interrupt_mask) = highest_priority_interrupt(interrupts_to_process);
+
+    gb.sp -= 1;
+    gb.write_byte(gb.sp, W(gb.pc.0 as u8));
+    gb.cycles_pending += 4;
+
+    gb.other_state.int_flag &= interrupt_mask;
+    gb.pc = interrupt_jump_addr;
+    profiler::on_interrupt(gb, gb.cycles_pending - dispatch_start);
+}
+
+//returns the vector and the mask that clears its flag, 0x0000 and no change when nothing is
+//pending
+fn highest_priority_interrupt(interrupts: u8) -> (W<u16>, u8) {
+    if (interrupts & INT_VBLANK) != 0 {
+        (W(0x40), !INT_VBLANK)
+    } else if (interrupts & INT_STAT) != 0 {
+        (W(0x48), !INT_STAT)
+    } else if (interrupts & INT_TIMER) != 0 {
+        (W(0x50), !INT_TIMER)
+    } else if (interrupts & INT_SERIAL) != 0 {
+        (W(0x58), !INT_SERIAL)
+    } else if (interrupts & INT_GAMEPAD) != 0 {
+        (W(0x60), !INT_GAMEPAD)
+    } else {
+        (W(0x0000), 0xff)
     }
 }
 
 pub fn process_timers(gb: &mut Gameboy) {
-    gb.other_state.counter_div += gb.cycles_pending as u16;
+    timer::tick(&mut gb.other_state, gb.cycles_pending);
 }
 
 pub fn process_oam_dma(gb: &mut Gameboy) {
@@ -431,7 +1029,7 @@ pub fn process_oam_dma(gb: &mut Gameboy) {
     //debug!("Copied {} bytes for OAM. (cur idx: 0x{:#02x})", gb.cycles_pending / 4, gb.other_state.oam_dma_cur_addr);
 }
 
-pub fn handle_input(gb: &mut Gameboy, input_keys: &Vec<InputKey>) {
+pub fn handle_input(gb: &mut Gameboy, input_keys: &[InputKey]) {
     for i in input_keys.iter().enumerate() {
         if i.1.get_state_just_changed() {
             if i.0 == 8 {
@@ -463,6 +1061,116 @@ pub fn handle_input(gb: &mut Gameboy, input_keys: &Vec<InputKey>) {
 
             gb.other_state.int_flag |= INT_GAMEPAD;
             gb.other_state.input_keys[i.0].copy_state_from_other(i.1);
+            if i.1.get_held() {
+                gb.other_state.stopped = false;
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPCODE_NOP: u8 = 0x00;
+    const OPCODE_INC_A: u8 = 0x3c;
+    const OPCODE_HALT: u8 = 0x76;
+    const OPCODE_DI: u8 = 0xf3;
+    const OPCODE_EI: u8 = 0xfb;
+
+    //rom-only cart with `program` at the entry point, started there with the bootrom off
+    fn gameboy_with_program(program: &[u8]) -> Gameboy {
+        let mut rom_data = vec![0u8; 0x8000];
+        rom_data[0x100..0x100 + program.len()].copy_from_slice(program);
+        let mut gb = Gameboy::from_bytes(SystemType::DMG, rom_data, vec![0u8; BOOTROM_SIZE as usize]).unwrap();
+        gb.other_state.bootrom_enabled = false;
+        gb.pc = W(0x100);
+        gb.sp = W(0xfffe);
+        gb
+    }
+
+    fn request_timer_interrupt(gb: &mut Gameboy) {
+        gb.other_state.int_enable = INT_TIMER;
+        gb.other_state.int_flag = INT_TIMER;
+    }
+
+    #[test]
+    fn halt_bug_runs_the_next_byte_twice() {
+        let mut gb = gameboy_with_program(&[OPCODE_HALT, OPCODE_INC_A, OPCODE_NOP]);
+        request_timer_interrupt(&mut gb);
+        gb.reg.a = W(0);
+
+        step(&mut gb).unwrap();
+        assert!(!gb.is_halted());
+        assert_eq!(gb.pc.0, 0x101);
+
+        step(&mut gb).unwrap();
+        assert_eq!(gb.reg.a.0, 1);
+        assert_eq!(gb.pc.0, 0x101);
+
+        step(&mut gb).unwrap();
+        assert_eq!(gb.reg.a.0, 2);
+        assert_eq!(gb.pc.0, 0x102);
+    }
+
+    #[test]
+    fn interrupt_dispatch_pushes_pc_and_jumps_to_the_vector() {
+        let mut gb = gameboy_with_program(&[OPCODE_NOP]);
+        request_timer_interrupt(&mut gb);
+        gb.ime = true;
+
+        step(&mut gb).unwrap();
+        assert_eq!(gb.pc.0, 0x50);
+        assert_eq!(gb.sp.0, 0xfffc);
+        assert_eq!(gb.read_byte_raw(W(0xfffd)).0, 0x01);
+        assert_eq!(gb.read_byte_raw(W(0xfffc)).0, 0x01);
+        assert!(!gb.ime);
+        assert_eq!(gb.other_state.int_flag & INT_TIMER, 0);
+        //one m-cycle of nop then five of dispatch
+        assert_eq!(gb.get_last_step_cycles(), 24);
+    }
+
+    #[test]
+    fn ie_push_cancels_the_dispatch() {
+        let mut gb = gameboy_with_program(&[OPCODE_NOP]);
+        request_timer_interrupt(&mut gb);
+        gb.ime = true;
+        //the high byte of pc (0x01) is pushed onto ie at 0xffff and clears the timer bit
+        gb.sp = W(0x0000);
+
+        step(&mut gb).unwrap();
+        assert_eq!(gb.pc.0, 0x0000);
+        assert_eq!(gb.sp.0, 0xfffe);
+        assert_eq!(gb.other_state.int_enable, 0x01);
+        assert_eq!(gb.read_byte_raw(W(0xfffe)).0, 0x01);
+        assert!(!gb.ime);
+        //the cancelled interrupt stays pending
+        assert_eq!(gb.other_state.int_flag & INT_TIMER, INT_TIMER);
+    }
+
+    #[test]
+    fn ei_takes_effect_after_the_next_instruction() {
+        let mut gb = gameboy_with_program(&[OPCODE_EI, OPCODE_NOP, OPCODE_NOP]);
+        request_timer_interrupt(&mut gb);
+
+        step(&mut gb).unwrap();
+        assert_eq!(gb.pc.0, 0x101);
+
+        step(&mut gb).unwrap();
+        assert_eq!(gb.pc.0, 0x50);
+        //returns to the instruction after the nop
+        assert_eq!(gb.read_byte_raw(W(0xfffc)).0, 0x02);
+    }
+
+    #[test]
+    fn di_right_after_ei_blocks_the_interrupt() {
+        let mut gb = gameboy_with_program(&[OPCODE_EI, OPCODE_DI, OPCODE_NOP]);
+        request_timer_interrupt(&mut gb);
+
+        step(&mut gb).unwrap();
+        step(&mut gb).unwrap();
+        step(&mut gb).unwrap();
+        assert_eq!(gb.pc.0, 0x103);
+        assert!(!gb.ime);
+    }
+}
@@ -0,0 +1,955 @@
+use std::sync::{Arc, Mutex};
+
+use super::error::GameboyError;
+use super::save_state::{StateReader, StateWriter};
+
+pub const CPU_CLOCK_HZ: u32 = 4194304;
+pub const DEFAULT_SAMPLE_RATE: u32 = 48000;
+
+const NR10: u8 = 0x10;
+const NR11: u8 = 0x11;
+const NR12: u8 = 0x12;
+const NR13: u8 = 0x13;
+const NR14: u8 = 0x14;
+const NR21: u8 = 0x16;
+const NR22: u8 = 0x17;
+const NR23: u8 = 0x18;
+const NR24: u8 = 0x19;
+const NR30: u8 = 0x1a;
+const NR31: u8 = 0x1b;
+const NR32: u8 = 0x1c;
+const NR33: u8 = 0x1d;
+const NR34: u8 = 0x1e;
+const NR41: u8 = 0x20;
+const NR42: u8 = 0x21;
+const NR43: u8 = 0x22;
+const NR44: u8 = 0x23;
+const NR50: u8 = 0x24;
+const NR51: u8 = 0x25;
+const NR52: u8 = 0x26;
+const WAVE_RAM_START: u8 = 0x30;
+const WAVE_RAM_END: u8 = 0x3f;
+
+//bits that always read back as 1, indexed from NR10
+const READ_MASKS: [u8; 0x17] = [
+    0x80, 0x3f, 0x00, 0xff, 0xbf, //NR10-NR14
+    0xff, 0x3f, 0x00, 0xff, 0xbf, //unused, NR21-NR24
+    0x7f, 0xff, 0x9f, 0xff, 0xbf, //NR30-NR34
+    0xff, 0xff, 0x00, 0x00, 0xbf, //unused, NR41-NR44
+    0x00, 0x00, 0x70, //NR50-NR52
+];
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 0],
+];
+
+const NOISE_DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+//longest each frequency timer can run, loaded states are clamped to these so a bad
+//timer can't overflow or spin in step
+const SQUARE_MAX_FREQ_TIMER: i32 = 2048 * 4;
+const WAVE_MAX_FREQ_TIMER: i32 = 2048 * 2 + 6;
+const NOISE_MAX_FREQ_TIMER: i32 = 112 << 15;
+
+const FRAME_SEQUENCER_DIV_BIT: u16 = 0x1000;
+
+pub trait AudioSink {
+    fn push_sample(&mut self, left: f32, right: f32);
+}
+
+#[derive(Clone, Default)]
+pub struct SampleBuffer {
+    samples: Arc<Mutex<Vec<f32>>>,
+}
+
+impl SampleBuffer {
+    pub fn new() -> SampleBuffer {
+        SampleBuffer::default()
+    }
+
+    //returns interleaved left/right samples pushed since the last call
+    pub fn take_samples(&self) -> Vec<f32> {
+        let mut samples = self.samples.lock().unwrap();
+        std::mem::take(&mut *samples)
+    }
+}
+
+impl AudioSink for SampleBuffer {
+    fn push_sample(&mut self, left: f32, right: f32) {
+        let mut samples = self.samples.lock().unwrap();
+        samples.push(left);
+        samples.push(right);
+    }
+}
+
+#[derive(Clone)]
+struct LengthCounter {
+    enabled: bool,
+    counter: u16,
+    max: u16,
+}
+
+impl LengthCounter {
+    fn new(max: u16) -> LengthCounter {
+        LengthCounter {
+            enabled: false,
+            counter: 0,
+            max,
+        }
+    }
+
+    fn load(&mut self, length_data: u8) {
+        self.counter = self.max - length_data as u16;
+    }
+
+    //returns true when the channel should be switched off
+    fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter == 0;
+        }
+        false
+    }
+
+    //extra_clock is set when the next frame sequencer step won't clock length,
+    //returns true when the channel should be switched off
+    fn write_control(&mut self, value: u8, extra_clock: bool) -> bool {
+        let was_enabled = self.enabled;
+        self.enabled = value & 0x40 != 0;
+
+        if extra_clock && !was_enabled && self.enabled && self.counter != 0 {
+            self.counter -= 1;
+            return self.counter == 0 && value & 0x80 == 0;
+        }
+        false
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.enabled);
+        w.write_u16(self.counter);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), GameboyError> {
+        self.enabled = r.read_bool()?;
+        self.counter = r.read_u16()?.min(self.max);
+        Ok(())
+    }
+
+    fn trigger(&mut self, extra_clock: bool) {
+        if self.counter == 0 {
+            self.counter = self.max;
+            if self.enabled && extra_clock {
+                self.counter -= 1;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct Envelope {
+    initial_volume: u8,
+    increase: bool,
+    period: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    fn write(&mut self, value: u8) {
+        self.initial_volume = value >> 4;
+        self.increase = value & 0x8 != 0;
+        self.period = value & 0x7;
+    }
+
+    fn trigger(&mut self) {
+        self.volume = self.initial_volume;
+        self.timer = self.period;
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.initial_volume);
+        w.write_bool(self.increase);
+        w.write_u8(self.period);
+        w.write_u8(self.volume);
+        w.write_u8(self.timer);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), GameboyError> {
+        self.initial_volume = r.read_u8()? & 0xf;
+        self.increase = r.read_bool()?;
+        self.period = r.read_u8()? & 0x7;
+        self.volume = r.read_u8()? & 0xf;
+        self.timer = r.read_u8()?;
+        Ok(())
+    }
+
+    fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.increase && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.increase && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct Sweep {
+    period: u8,
+    negate: bool,
+    shift: u8,
+    timer: u8,
+    enabled: bool,
+    shadow_frequency: u16,
+    negate_used: bool,
+}
+
+impl Sweep {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.period);
+        w.write_bool(self.negate);
+        w.write_u8(self.shift);
+        w.write_u8(self.timer);
+        w.write_bool(self.enabled);
+        w.write_u16(self.shadow_frequency);
+        w.write_bool(self.negate_used);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), GameboyError> {
+        self.period = r.read_u8()? & 0x7;
+        self.negate = r.read_bool()?;
+        self.shift = r.read_u8()? & 0x7;
+        self.timer = r.read_u8()?;
+        self.enabled = r.read_bool()?;
+        self.shadow_frequency = r.read_u16()? & 0x7ff;
+        self.negate_used = r.read_bool()?;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct SquareChannel {
+    enabled: bool,
+    dac_enabled: bool,
+    duty: u8,
+    duty_pos: u8,
+    frequency: u16,
+    freq_timer: i32,
+    length: LengthCounter,
+    envelope: Envelope,
+    sweep: Option<Sweep>,
+}
+
+impl SquareChannel {
+    fn new(has_sweep: bool) -> SquareChannel {
+        SquareChannel {
+            enabled: false,
+            dac_enabled: false,
+            duty: 0,
+            duty_pos: 0,
+            frequency: 0,
+            freq_timer: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::default(),
+            sweep: if has_sweep { Some(Sweep::default()) } else { None },
+        }
+    }
+
+    fn period(&self) -> i32 {
+        (2048 - self.frequency as i32) * 4
+    }
+
+    fn step(&mut self, cycles: u32) {
+        self.freq_timer -= cycles as i32;
+        while self.freq_timer <= 0 {
+            self.freq_timer += self.period();
+            self.duty_pos = (self.duty_pos + 1) & 0x7;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        DUTY_TABLE[self.duty as usize][self.duty_pos as usize] * self.envelope.volume
+    }
+
+    fn write_sweep(&mut self, value: u8) {
+        if let Some(sweep) = &mut self.sweep {
+            sweep.period = (value >> 4) & 0x7;
+            sweep.negate = value & 0x8 != 0;
+            sweep.shift = value & 0x7;
+
+            //leaving negate mode after a negated calculation switches the channel off
+            if !sweep.negate && sweep.negate_used {
+                self.enabled = false;
+            }
+        }
+    }
+
+    fn write_envelope(&mut self, value: u8) {
+        self.envelope.write(value);
+        self.dac_enabled = value & 0xf8 != 0;
+        if !self.dac_enabled {
+            self.enabled = false;
+        }
+    }
+
+    fn calculate_sweep_frequency(&mut self) -> u16 {
+        let sweep = self.sweep.as_mut().unwrap();
+        let delta = sweep.shadow_frequency >> sweep.shift;
+        let new_frequency = if sweep.negate {
+            sweep.negate_used = true;
+            sweep.shadow_frequency.wrapping_sub(delta)
+        } else {
+            sweep.shadow_frequency + delta
+        };
+
+        if new_frequency > 2047 {
+            self.enabled = false;
+        }
+        new_frequency
+    }
+
+    fn clock_sweep(&mut self) {
+        let sweep = match &mut self.sweep {
+            Some(sweep) => sweep,
+            None => return,
+        };
+
+        if sweep.timer > 0 {
+            sweep.timer -= 1;
+        }
+
+        if sweep.timer == 0 {
+            sweep.timer = if sweep.period == 0 { 8 } else { sweep.period };
+
+            if sweep.enabled && sweep.period != 0 {
+                let shift = sweep.shift;
+                let new_frequency = self.calculate_sweep_frequency();
+                if new_frequency <= 2047 && shift != 0 {
+                    self.sweep.as_mut().unwrap().shadow_frequency = new_frequency;
+                    self.frequency = new_frequency;
+                    self.calculate_sweep_frequency();
+                }
+            }
+        }
+    }
+
+    fn trigger(&mut self, extra_clock: bool) {
+        self.enabled = self.dac_enabled;
+        self.freq_timer = self.period();
+        self.envelope.trigger();
+        self.length.trigger(extra_clock);
+
+        if let Some(sweep) = &mut self.sweep {
+            sweep.shadow_frequency = self.frequency;
+            sweep.timer = if sweep.period == 0 { 8 } else { sweep.period };
+            sweep.enabled = sweep.period != 0 || sweep.shift != 0;
+            sweep.negate_used = false;
+
+            if sweep.shift != 0 {
+                self.calculate_sweep_frequency();
+            }
+        }
+    }
+
+    fn power_off(&mut self) {
+        let length_counter = self.length.counter;
+        *self = SquareChannel::new(self.sweep.is_some());
+        self.length.counter = length_counter;
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.enabled);
+        w.write_bool(self.dac_enabled);
+        w.write_u8(self.duty);
+        w.write_u8(self.duty_pos);
+        w.write_u16(self.frequency);
+        w.write_i32(self.freq_timer);
+        self.length.save_state(w);
+        self.envelope.save_state(w);
+        if let Some(sweep) = &self.sweep {
+            sweep.save_state(w);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), GameboyError> {
+        self.enabled = r.read_bool()?;
+        self.dac_enabled = r.read_bool()?;
+        self.duty = r.read_u8()? & 0x3;
+        self.duty_pos = r.read_u8()? & 0x7;
+        self.frequency = r.read_u16()? & 0x7ff;
+        self.freq_timer = r.read_i32()?.clamp(0, SQUARE_MAX_FREQ_TIMER);
+        self.length.load_state(r)?;
+        self.envelope.load_state(r)?;
+        if let Some(sweep) = &mut self.sweep {
+            sweep.load_state(r)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct WaveChannel {
+    enabled: bool,
+    dac_enabled: bool,
+    volume_code: u8,
+    frequency: u16,
+    freq_timer: i32,
+    position: u8,
+    sample_buffer: u8,
+    length: LengthCounter,
+    wave_ram: [u8; 16],
+}
+
+impl WaveChannel {
+    fn new() -> WaveChannel {
+        WaveChannel {
+            enabled: false,
+            dac_enabled: false,
+            volume_code: 0,
+            frequency: 0,
+            freq_timer: 0,
+            position: 0,
+            sample_buffer: 0,
+            length: LengthCounter::new(256),
+            wave_ram: [0u8; 16],
+        }
+    }
+
+    fn period(&self) -> i32 {
+        (2048 - self.frequency as i32) * 2
+    }
+
+    fn step(&mut self, cycles: u32) {
+        self.freq_timer -= cycles as i32;
+        while self.freq_timer <= 0 {
+            self.freq_timer += self.period();
+            self.position = (self.position + 1) & 0x1f;
+
+            let wave_byte = self.wave_ram[(self.position >> 1) as usize];
+            self.sample_buffer = if self.position & 0x1 == 0 { wave_byte >> 4 } else { wave_byte & 0xf };
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if !self.enabled || self.volume_code == 0 {
+            return 0;
+        }
+        self.sample_buffer >> (self.volume_code - 1)
+    }
+
+    fn trigger(&mut self, extra_clock: bool) {
+        self.enabled = self.dac_enabled;
+        //the first sample is fetched after a short delay
+        self.freq_timer = self.period() + 6;
+        self.position = 0;
+        self.length.trigger(extra_clock);
+    }
+
+    //while the channel is playing the cpu only sees the byte currently being read
+    fn read_wave_ram(&self, index: u8) -> u8 {
+        if self.enabled {
+            return self.wave_ram[(self.position >> 1) as usize];
+        }
+        self.wave_ram[index as usize]
+    }
+
+    fn write_wave_ram(&mut self, index: u8, value: u8) {
+        if self.enabled {
+            self.wave_ram[(self.position >> 1) as usize] = value;
+        } else {
+            self.wave_ram[index as usize] = value;
+        }
+    }
+
+    fn power_off(&mut self) {
+        let length_counter = self.length.counter;
+        let wave_ram = self.wave_ram;
+        *self = WaveChannel::new();
+        self.length.counter = length_counter;
+        self.wave_ram = wave_ram;
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.enabled);
+        w.write_bool(self.dac_enabled);
+        w.write_u8(self.volume_code);
+        w.write_u16(self.frequency);
+        w.write_i32(self.freq_timer);
+        w.write_u8(self.position);
+        w.write_u8(self.sample_buffer);
+        self.length.save_state(w);
+        w.write_bytes(&self.wave_ram);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), GameboyError> {
+        self.enabled = r.read_bool()?;
+        self.dac_enabled = r.read_bool()?;
+        self.volume_code = r.read_u8()? & 0x3;
+        self.frequency = r.read_u16()? & 0x7ff;
+        self.freq_timer = r.read_i32()?.clamp(0, WAVE_MAX_FREQ_TIMER);
+        self.position = r.read_u8()? & 0x1f;
+        self.sample_buffer = r.read_u8()? & 0xf;
+        self.length.load_state(r)?;
+        r.read_bytes_into(&mut self.wave_ram, "wave ram")
+    }
+}
+
+#[derive(Clone)]
+struct NoiseChannel {
+    enabled: bool,
+    dac_enabled: bool,
+    clock_shift: u8,
+    width_mode_7bit: bool,
+    divisor_code: u8,
+    lfsr: u16,
+    freq_timer: i32,
+    length: LengthCounter,
+    envelope: Envelope,
+}
+
+impl NoiseChannel {
+    fn new() -> NoiseChannel {
+        NoiseChannel {
+            enabled: false,
+            dac_enabled: false,
+            clock_shift: 0,
+            width_mode_7bit: false,
+            divisor_code: 0,
+            lfsr: 0x7fff,
+            freq_timer: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::default(),
+        }
+    }
+
+    fn period(&self) -> i32 {
+        (NOISE_DIVISORS[self.divisor_code as usize] << self.clock_shift) as i32
+    }
+
+    fn step(&mut self, cycles: u32) {
+        self.freq_timer -= cycles as i32;
+        while self.freq_timer <= 0 {
+            self.freq_timer += self.period();
+
+            let xor_bit = (self.lfsr & 0x1) ^ ((self.lfsr >> 1) & 0x1);
+            self.lfsr = (self.lfsr >> 1) | (xor_bit << 14);
+            if self.width_mode_7bit {
+                self.lfsr = (self.lfsr & !0x40) | (xor_bit << 6);
+            }
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if !self.enabled || self.lfsr & 0x1 != 0 {
+            return 0;
+        }
+        self.envelope.volume
+    }
+
+    fn write_envelope(&mut self, value: u8) {
+        self.envelope.write(value);
+        self.dac_enabled = value & 0xf8 != 0;
+        if !self.dac_enabled {
+            self.enabled = false;
+        }
+    }
+
+    fn trigger(&mut self, extra_clock: bool) {
+        self.enabled = self.dac_enabled;
+        self.freq_timer = self.period();
+        self.lfsr = 0x7fff;
+        self.envelope.trigger();
+        self.length.trigger(extra_clock);
+    }
+
+    fn power_off(&mut self) {
+        let length_counter = self.length.counter;
+        *self = NoiseChannel::new();
+        self.length.counter = length_counter;
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.enabled);
+        w.write_bool(self.dac_enabled);
+        w.write_u8(self.clock_shift);
+        w.write_bool(self.width_mode_7bit);
+        w.write_u8(self.divisor_code);
+        w.write_u16(self.lfsr);
+        w.write_i32(self.freq_timer);
+        self.length.save_state(w);
+        self.envelope.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), GameboyError> {
+        self.enabled = r.read_bool()?;
+        self.dac_enabled = r.read_bool()?;
+        self.clock_shift = r.read_u8()? & 0xf;
+        self.width_mode_7bit = r.read_bool()?;
+        self.divisor_code = r.read_u8()? & 0x7;
+        self.lfsr = r.read_u16()? & 0x7fff;
+        self.freq_timer = r.read_i32()?.clamp(0, NOISE_MAX_FREQ_TIMER);
+        self.length.load_state(r)?;
+        self.envelope.load_state(r)
+    }
+}
+
+pub struct APU {
+    powered: bool,
+    square_1: SquareChannel,
+    square_2: SquareChannel,
+    wave: WaveChannel,
+    noise: NoiseChannel,
+
+    regs: [u8; 0x17], //raw NR10-NR52 values, for reads
+    left_volume: u8, //nr50
+    right_volume: u8,
+    panning: u8, //nr51
+
+    frame_seq_step: u8,
+    last_div_bit: bool,
+
+    sample_rate: u32,
+    sample_counter: u64,
+    sink: Option<Box<dyn AudioSink>>,
+}
+
+impl Default for APU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl APU {
+    pub fn new() -> APU {
+        APU {
+            powered: false,
+            square_1: SquareChannel::new(true),
+            square_2: SquareChannel::new(false),
+            wave: WaveChannel::new(),
+            noise: NoiseChannel::new(),
+
+            regs: [0u8; 0x17],
+            left_volume: 0,
+            right_volume: 0,
+            panning: 0,
+
+            frame_seq_step: 0,
+            last_div_bit: false,
+
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            sample_counter: 0,
+            sink: None,
+        }
+    }
+
+    pub fn set_sink(&mut self, sink: Option<Box<dyn AudioSink>>) {
+        self.sink = sink;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.sample_counter = 0;
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn read_register(&self, addr: u8) -> u8 {
+        match addr {
+            NR52 => {
+                let mut val = READ_MASKS[(NR52 - NR10) as usize] | ((self.powered as u8) << 7);
+                val |= (self.noise.enabled as u8) << 3;
+                val |= (self.wave.enabled as u8) << 2;
+                val |= (self.square_2.enabled as u8) << 1;
+                val | (self.square_1.enabled as u8)
+            }
+            NR10..=NR51 => self.regs[(addr - NR10) as usize] | READ_MASKS[(addr - NR10) as usize],
+            WAVE_RAM_START..=WAVE_RAM_END => self.wave.read_wave_ram(addr - WAVE_RAM_START),
+            _ => super::UNDEFINED_READ,
+        }
+    }
+
+    pub fn write_register(&mut self, addr: u8, value: u8) {
+        if let WAVE_RAM_START..=WAVE_RAM_END = addr {
+            self.wave.write_wave_ram(addr - WAVE_RAM_START, value);
+            return;
+        }
+
+        if addr == NR52 {
+            self.write_power(value & 0x80 != 0);
+            return;
+        }
+
+        if !self.powered {
+            //on dmg the length counters stay writable while powered off
+            match addr {
+                NR11 => self.square_1.length.load(value & 0x3f),
+                NR21 => self.square_2.length.load(value & 0x3f),
+                NR31 => self.wave.length.load(value),
+                NR41 => self.noise.length.load(value & 0x3f),
+                _ => {}
+            }
+            return;
+        }
+
+        if let NR10..=NR51 = addr {
+            self.regs[(addr - NR10) as usize] = value;
+        }
+
+        //length is only clocked on even steps, so an odd next step means we're in the first half
+        let extra_clock = self.frame_seq_step & 0x1 == 1;
+
+        match addr {
+            NR10 => {
+                self.square_1.write_sweep(value);
+            }
+            NR11 => {
+                self.square_1.duty = value >> 6;
+                self.square_1.length.load(value & 0x3f);
+            }
+            NR12 => {
+                self.square_1.write_envelope(value);
+            }
+            NR13 => {
+                self.square_1.frequency = (self.square_1.frequency & 0x700) | value as u16;
+            }
+            NR14 => {
+                self.square_1.frequency = (self.square_1.frequency & 0xff) | ((value as u16 & 0x7) << 8);
+                if self.square_1.length.write_control(value, extra_clock) {
+                    self.square_1.enabled = false;
+                }
+                if value & 0x80 != 0 {
+                    self.square_1.trigger(extra_clock);
+                }
+            }
+            NR21 => {
+                self.square_2.duty = value >> 6;
+                self.square_2.length.load(value & 0x3f);
+            }
+            NR22 => {
+                self.square_2.write_envelope(value);
+            }
+            NR23 => {
+                self.square_2.frequency = (self.square_2.frequency & 0x700) | value as u16;
+            }
+            NR24 => {
+                self.square_2.frequency = (self.square_2.frequency & 0xff) | ((value as u16 & 0x7) << 8);
+                if self.square_2.length.write_control(value, extra_clock) {
+                    self.square_2.enabled = false;
+                }
+                if value & 0x80 != 0 {
+                    self.square_2.trigger(extra_clock);
+                }
+            }
+            NR30 => {
+                self.wave.dac_enabled = value & 0x80 != 0;
+                if !self.wave.dac_enabled {
+                    self.wave.enabled = false;
+                }
+            }
+            NR31 => {
+                self.wave.length.load(value);
+            }
+            NR32 => {
+                self.wave.volume_code = (value >> 5) & 0x3;
+            }
+            NR33 => {
+                self.wave.frequency = (self.wave.frequency & 0x700) | value as u16;
+            }
+            NR34 => {
+                self.wave.frequency = (self.wave.frequency & 0xff) | ((value as u16 & 0x7) << 8);
+                if self.wave.length.write_control(value, extra_clock) {
+                    self.wave.enabled = false;
+                }
+                if value & 0x80 != 0 {
+                    self.wave.trigger(extra_clock);
+                }
+            }
+            NR41 => {
+                self.noise.length.load(value & 0x3f);
+            }
+            NR42 => {
+                self.noise.write_envelope(value);
+            }
+            NR43 => {
+                self.noise.clock_shift = value >> 4;
+                self.noise.width_mode_7bit = value & 0x8 != 0;
+                self.noise.divisor_code = value & 0x7;
+            }
+            NR44 => {
+                if self.noise.length.write_control(value, extra_clock) {
+                    self.noise.enabled = false;
+                }
+                if value & 0x80 != 0 {
+                    self.noise.trigger(extra_clock);
+                }
+            }
+            NR50 => {
+                self.left_volume = (value >> 4) & 0x7;
+                self.right_volume = value & 0x7;
+            }
+            NR51 => {
+                self.panning = value;
+            }
+            _ => {}
+        }
+    }
+
+    fn write_power(&mut self, powered: bool) {
+        if self.powered && !powered {
+            self.square_1.power_off();
+            self.square_2.power_off();
+            self.wave.power_off();
+            self.noise.power_off();
+            self.regs = [0u8; 0x17];
+            self.left_volume = 0;
+            self.right_volume = 0;
+            self.panning = 0;
+        } else if !self.powered && powered {
+            self.frame_seq_step = 0;
+            self.square_1.duty_pos = 0;
+            self.square_2.duty_pos = 0;
+            self.wave.sample_buffer = 0;
+        }
+        self.powered = powered;
+    }
+
+    fn clock_frame_sequencer(&mut self) {
+        if self.frame_seq_step & 0x1 == 0 {
+            if self.square_1.length.clock() {
+                self.square_1.enabled = false;
+            }
+            if self.square_2.length.clock() {
+                self.square_2.enabled = false;
+            }
+            if self.wave.length.clock() {
+                self.wave.enabled = false;
+            }
+            if self.noise.length.clock() {
+                self.noise.enabled = false;
+            }
+        }
+
+        if self.frame_seq_step == 2 || self.frame_seq_step == 6 {
+            self.square_1.clock_sweep();
+        }
+
+        if self.frame_seq_step == 7 {
+            self.square_1.envelope.clock();
+            self.square_2.envelope.clock();
+            self.noise.envelope.clock();
+        }
+
+        self.frame_seq_step = (self.frame_seq_step + 1) & 0x7;
+    }
+
+    fn mix(&self) -> (f32, f32) {
+        let channels = [
+            (self.square_1.dac_enabled, self.square_1.output()),
+            (self.square_2.dac_enabled, self.square_2.output()),
+            (self.wave.dac_enabled, self.wave.output()),
+            (self.noise.dac_enabled, self.noise.output()),
+        ];
+
+        let mut left = 0f32;
+        let mut right = 0f32;
+        for (idx, (dac_enabled, digital)) in channels.iter().enumerate() {
+            if !dac_enabled {
+                continue;
+            }
+
+            let analog = (*digital as f32 / 7.5) - 1.0;
+            if self.panning & (0x10 << idx) != 0 {
+                left += analog;
+            }
+            if self.panning & (0x1 << idx) != 0 {
+                right += analog;
+            }
+        }
+
+        left *= (self.left_volume + 1) as f32 / 8.0;
+        right *= (self.right_volume + 1) as f32 / 8.0;
+        (left / 4.0, right / 4.0)
+    }
+
+    //div is the full internal divider, the frame sequencer runs off the falling edge of its bit 12
+    pub fn run_cycles(&mut self, cycles: u32, div: u16) {
+        let div_bit = div & FRAME_SEQUENCER_DIV_BIT != 0;
+
+        if self.powered {
+            if self.last_div_bit && !div_bit {
+                self.clock_frame_sequencer();
+            }
+
+            self.square_1.step(cycles);
+            self.square_2.step(cycles);
+            self.wave.step(cycles);
+            self.noise.step(cycles);
+        }
+        self.last_div_bit = div_bit;
+
+        if self.sink.is_none() {
+            return;
+        }
+
+        self.sample_counter += cycles as u64 * self.sample_rate as u64;
+        while self.sample_counter >= CPU_CLOCK_HZ as u64 {
+            self.sample_counter -= CPU_CLOCK_HZ as u64;
+
+            let (left, right) = if self.powered { self.mix() } else { (0f32, 0f32) };
+            if let Some(sink) = &mut self.sink {
+                sink.push_sample(left, right);
+            }
+        }
+    }
+
+    //the sink and sample rate belong to the host, not the emulated machine
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.powered);
+        self.square_1.save_state(w);
+        self.square_2.save_state(w);
+        self.wave.save_state(w);
+        self.noise.save_state(w);
+
+        w.write_bytes(&self.regs);
+        w.write_u8(self.left_volume);
+        w.write_u8(self.right_volume);
+        w.write_u8(self.panning);
+        w.write_u8(self.frame_seq_step);
+        w.write_bool(self.last_div_bit);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), GameboyError> {
+        self.powered = r.read_bool()?;
+        self.square_1.load_state(r)?;
+        self.square_2.load_state(r)?;
+        self.wave.load_state(r)?;
+        self.noise.load_state(r)?;
+
+        r.read_bytes_into(&mut self.regs, "apu registers")?;
+        self.left_volume = r.read_u8()? & 0x7;
+        self.right_volume = r.read_u8()? & 0x7;
+        self.panning = r.read_u8()?;
+        self.frame_seq_step = r.read_u8()? & 0x7;
+        self.last_div_bit = r.read_bool()?;
+        self.sample_counter = 0;
+        Ok(())
+    }
+}
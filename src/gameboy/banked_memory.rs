@@ -1,5 +1,8 @@
 use log::{debug, warn};
 
+use super::error::GameboyError;
+use super::save_state::{StateReader, StateWriter};
+
 pub struct BankedMemory {
     read_only: bool,
     bank_count: u16,
@@ -47,7 +50,7 @@ impl BankedMemory {
             ret.memory_data.iter_mut().for_each(|i| *i = rand::random());
         }
 
-        return ret;
+        ret
     }
 
     pub fn switch_bank(&mut self, new_bank: u16) {
@@ -71,9 +74,9 @@ impl BankedMemory {
                 "possible bug, tried to read beyond banked memory size, (addr: {:#06x}, in: {})",
                 addr, self.name
             );
-            return crate::gameboy::UNDEFINED_READ;
+            crate::gameboy::UNDEFINED_READ
         } else {
-            return self.memory_data[self.current_bank as usize * self.bank_size as usize + addr as usize];
+            self.memory_data[self.current_bank as usize * self.bank_size as usize + addr as usize]
         }
     }
 
@@ -86,7 +89,6 @@ impl BankedMemory {
                 "possible bug, tried to write beyond banked memory size, (addr: {:#06x}, in: {})",
                 addr, self.name
             );
-            return;
         } else {
             self.memory_data[self.current_bank as usize * self.bank_size as usize + addr as usize] = value;
         }
@@ -95,4 +97,18 @@ impl BankedMemory {
     pub fn get_bank_count(&self) -> u16 {
         self.bank_count
     }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_u16(self.current_bank);
+        w.write_bytes(&self.memory_data);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), GameboyError> {
+        let bank = r.read_u16()?;
+        if bank >= self.bank_count {
+            return Err(GameboyError::BadSaveState { reason: format!("bank {} out of range in {}", bank, self.name) });
+        }
+        self.current_bank = bank;
+        r.read_bytes_into(&mut self.memory_data, &self.name)
+    }
 }
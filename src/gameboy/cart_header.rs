@@ -0,0 +1,247 @@
+use std::fmt;
+
+use super::error::GameboyError;
+
+const HEADER_END: usize = 0x150;
+const TITLE_START: usize = 0x134;
+const TITLE_END: usize = 0x143;
+const NEW_LICENSEE_CODE: usize = 0x144;
+const SGB_FLAG: usize = 0x146;
+const CART_TYPE: usize = 0x147;
+const ROM_SIZE: usize = 0x148;
+const RAM_SIZE: usize = 0x149;
+const DESTINATION_CODE: usize = 0x14a;
+const OLD_LICENSEE_CODE: usize = 0x14b;
+const ROM_VERSION: usize = 0x14c;
+const HEADER_CHECKSUM: usize = 0x14d;
+const GLOBAL_CHECKSUM: usize = 0x14e;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MapperType {
+    NoMapper,
+    MBC1,
+    MBC2,
+    MBC3,
+    MBC5,
+    Unsupported,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CgbSupport {
+    DmgOnly,
+    CgbCompatible,
+    CgbOnly,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Licensee {
+    Old(u8),
+    New(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct CartHeader {
+    pub title: String,
+    pub cgb_support: CgbSupport,
+    pub sgb_support: bool,
+    pub cart_type: u8,
+    pub rom_size_code: u8,
+    pub ram_size_code: u8,
+    pub japanese: bool,
+    pub licensee: Licensee,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub computed_header_checksum: u8,
+    pub global_checksum: u16,
+    pub computed_global_checksum: u16,
+}
+
+impl CartHeader {
+    pub fn parse(rom_data: &[u8]) -> Result<CartHeader, GameboyError> {
+        if rom_data.len() < HEADER_END {
+            return Err(GameboyError::RomTooSmall { size: rom_data.len() });
+        }
+
+        let cgb_support = match rom_data[TITLE_END] {
+            0x80 => CgbSupport::CgbCompatible,
+            0xC0 => CgbSupport::CgbOnly,
+            _ => CgbSupport::DmgOnly,
+        };
+
+        //cgb carts reuse the last title byte as the cgb flag
+        let title_end = if cgb_support == CgbSupport::DmgOnly { TITLE_END + 1 } else { TITLE_END };
+        let title = rom_data[TITLE_START..title_end]
+            .iter()
+            .take_while(|c| **c != 0)
+            .map(|c| if c.is_ascii_graphic() || *c == b' ' { *c as char } else { '?' })
+            .collect();
+
+        let licensee = if rom_data[OLD_LICENSEE_CODE] == 0x33 {
+            Licensee::New(String::from_utf8_lossy(&rom_data[NEW_LICENSEE_CODE..NEW_LICENSEE_CODE + 2]).into_owned())
+        } else {
+            Licensee::Old(rom_data[OLD_LICENSEE_CODE])
+        };
+
+        let mut computed_header_checksum = 0u8;
+        for byte in &rom_data[TITLE_START..HEADER_CHECKSUM] {
+            computed_header_checksum = computed_header_checksum.wrapping_sub(*byte).wrapping_sub(1);
+        }
+
+        let mut computed_global_checksum = 0u16;
+        for (idx, byte) in rom_data.iter().enumerate() {
+            if idx != GLOBAL_CHECKSUM && idx != GLOBAL_CHECKSUM + 1 {
+                computed_global_checksum = computed_global_checksum.wrapping_add(*byte as u16);
+            }
+        }
+
+        Ok(CartHeader {
+            title,
+            cgb_support,
+            sgb_support: rom_data[SGB_FLAG] == 0x03,
+            cart_type: rom_data[CART_TYPE],
+            rom_size_code: rom_data[ROM_SIZE],
+            ram_size_code: rom_data[RAM_SIZE],
+            japanese: rom_data[DESTINATION_CODE] == 0x00,
+            licensee,
+            version: rom_data[ROM_VERSION],
+            header_checksum: rom_data[HEADER_CHECKSUM],
+            computed_header_checksum,
+            global_checksum: u16::from_be_bytes([rom_data[GLOBAL_CHECKSUM], rom_data[GLOBAL_CHECKSUM + 1]]),
+            computed_global_checksum,
+        })
+    }
+
+    //blank header of a rom-only cart, for cores that aren't built from a rom image
+    pub fn rom_only() -> CartHeader {
+        CartHeader {
+            title: String::new(),
+            cgb_support: CgbSupport::DmgOnly,
+            sgb_support: false,
+            cart_type: 0x00,
+            rom_size_code: 0x00,
+            ram_size_code: 0x00,
+            japanese: false,
+            licensee: Licensee::Old(0x00),
+            version: 0,
+            header_checksum: 0,
+            computed_header_checksum: 0,
+            global_checksum: 0,
+            computed_global_checksum: 0,
+        }
+    }
+
+    pub fn mapper_type(&self) -> MapperType {
+        match self.cart_type {
+            0x00 => MapperType::NoMapper,
+            0x01..=0x03 => MapperType::MBC1,
+            0x05..=0x06 => MapperType::MBC2,
+            0x0F..=0x13 => MapperType::MBC3,
+            0x19..=0x1E => MapperType::MBC5,
+            _ => MapperType::Unsupported,
+        }
+    }
+
+    pub fn cart_type_name(&self) -> &'static str {
+        match self.cart_type {
+            0x00 => "ROM ONLY",
+            0x01 => "MBC1",
+            0x02 => "MBC1+RAM",
+            0x03 => "MBC1+RAM+BATTERY",
+            0x05 => "MBC2",
+            0x06 => "MBC2+BATTERY",
+            0x08 => "ROM+RAM",
+            0x09 => "ROM+RAM+BATTERY",
+            0x0B => "MMM01",
+            0x0C => "MMM01+RAM",
+            0x0D => "MMM01+RAM+BATTERY",
+            0x0F => "MBC3+TIMER+BATTERY",
+            0x10 => "MBC3+TIMER+RAM+BATTERY",
+            0x11 => "MBC3",
+            0x12 => "MBC3+RAM",
+            0x13 => "MBC3+RAM+BATTERY",
+            0x19 => "MBC5",
+            0x1A => "MBC5+RAM",
+            0x1B => "MBC5+RAM+BATTERY",
+            0x1C => "MBC5+RUMBLE",
+            0x1D => "MBC5+RUMBLE+RAM",
+            0x1E => "MBC5+RUMBLE+RAM+BATTERY",
+            0x20 => "MBC6",
+            0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+            0xFC => "POCKET CAMERA",
+            0xFD => "BANDAI TAMA5",
+            0xFE => "HuC3",
+            0xFF => "HuC1+RAM+BATTERY",
+            _ => "UNKNOWN",
+        }
+    }
+
+    pub fn has_battery(&self) -> bool {
+        matches!(self.cart_type, 0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF)
+    }
+
+    pub fn has_rtc(&self) -> bool {
+        matches!(self.cart_type, 0x0F | 0x10)
+    }
+
+    pub fn has_rumble(&self) -> bool {
+        matches!(self.cart_type, 0x1C..=0x1E)
+    }
+
+    pub fn rom_size(&self) -> Option<usize> {
+        match self.rom_size_code {
+            0x00..=0x08 => Some(0x8000 << self.rom_size_code),
+            0x52 => Some(0x12_0000),
+            0x53 => Some(0x14_0000),
+            0x54 => Some(0x18_0000),
+            _ => None,
+        }
+    }
+
+    pub fn ram_size(&self) -> Option<usize> {
+        match self.ram_size_code {
+            0x00 => Some(0),
+            0x01 => Some(0x800),
+            0x02 => Some(0x2000),
+            0x03 => Some(0x8000),
+            0x04 => Some(0x20000),
+            0x05 => Some(0x10000),
+            _ => None,
+        }
+    }
+
+    pub fn header_checksum_valid(&self) -> bool {
+        self.header_checksum == self.computed_header_checksum
+    }
+
+    pub fn global_checksum_valid(&self) -> bool {
+        self.global_checksum == self.computed_global_checksum
+    }
+}
+
+impl fmt::Display for CartHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Title: {}  Version: {}", self.title, self.version)?;
+        writeln!(f, "Type: {:#04x} ({})", self.cart_type, self.cart_type_name())?;
+        match self.rom_size() {
+            Some(size) => write!(f, "ROM: {} KiB", size / 1024)?,
+            None => write!(f, "ROM: unknown ({:#04x})", self.rom_size_code)?,
+        }
+        match self.ram_size() {
+            Some(size) => writeln!(f, "  RAM: {} KiB", size / 1024)?,
+            None => writeln!(f, "  RAM: unknown ({:#04x})", self.ram_size_code)?,
+        }
+        writeln!(f, "CGB: {:?}  SGB: {}  Japanese: {}", self.cgb_support, self.sgb_support, self.japanese)?;
+        match &self.licensee {
+            Licensee::Old(code) => writeln!(f, "Licensee: {:#04x}", code)?,
+            Licensee::New(code) => writeln!(f, "Licensee: \"{}\"", code)?,
+        }
+        write!(
+            f,
+            "Header checksum: {:#04x} ({})  Global checksum: {:#06x} ({})",
+            self.header_checksum,
+            if self.header_checksum_valid() { "ok" } else { "BAD" },
+            self.global_checksum,
+            if self.global_checksum_valid() { "ok" } else { "BAD" }
+        )
+    }
+}
@@ -0,0 +1,661 @@
+use core::num::Wrapping as W;
+use std::fmt;
+use std::fmt::Write as _;
+
+use super::registers::Registers;
+use super::{CpuLockup, Gameboy, IO_REG_END, IO_REG_START};
+
+const DEFAULT_DISASSEMBLE_COUNT: u16 = 10;
+const DEFAULT_DUMP_LENGTH: u16 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Register {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+    PC,
+}
+
+impl Register {
+    pub fn parse(name: &str) -> Option<Register> {
+        match name.to_ascii_lowercase().as_str() {
+            "a" => Some(Register::A),
+            "f" => Some(Register::F),
+            "b" => Some(Register::B),
+            "c" => Some(Register::C),
+            "d" => Some(Register::D),
+            "e" => Some(Register::E),
+            "h" => Some(Register::H),
+            "l" => Some(Register::L),
+            "af" => Some(Register::AF),
+            "bc" => Some(Register::BC),
+            "de" => Some(Register::DE),
+            "hl" => Some(Register::HL),
+            "sp" => Some(Register::SP),
+            "pc" => Some(Register::PC),
+            _ => None,
+        }
+    }
+
+    fn value(&self, reg: &Registers, sp: u16, pc: u16) -> u16 {
+        match self {
+            Register::A => reg.a.0 as u16,
+            Register::F => reg.get_f().0 as u16,
+            Register::B => reg.b.0 as u16,
+            Register::C => reg.c.0 as u16,
+            Register::D => reg.d.0 as u16,
+            Register::E => reg.e.0 as u16,
+            Register::H => reg.h.0 as u16,
+            Register::L => reg.l.0 as u16,
+            Register::AF => reg.get_af().0,
+            Register::BC => reg.get_bc().0,
+            Register::DE => reg.get_de().0,
+            Register::HL => reg.get_hl().0,
+            Register::SP => sp,
+            Register::PC => pc,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+impl Comparison {
+    pub fn parse(op: &str) -> Option<Comparison> {
+        match op {
+            "==" => Some(Comparison::Equal),
+            "!=" => Some(Comparison::NotEqual),
+            "<" => Some(Comparison::Less),
+            "<=" => Some(Comparison::LessEqual),
+            ">" => Some(Comparison::Greater),
+            ">=" => Some(Comparison::GreaterEqual),
+            _ => None,
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterEqual => ">=",
+        }
+    }
+}
+
+//register compared against a constant, checked when the breakpoint address is reached
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl Condition {
+    fn holds(&self, reg: &Registers, sp: u16, pc: u16) -> bool {
+        let current = self.register.value(reg, sp, pc);
+        match self.comparison {
+            Comparison::Equal => current == self.value,
+            Comparison::NotEqual => current != self.value,
+            Comparison::Less => current < self.value,
+            Comparison::LessEqual => current <= self.value,
+            Comparison::Greater => current > self.value,
+            Comparison::GreaterEqual => current >= self.value,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} {} ${:x}", self.register, self.comparison.symbol(), self.value)
+    }
+}
+
+//stops before the instruction at `addr` runs. a bank only applies to rom addresses and
+//makes the breakpoint ignore other banks switched in at the same address
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakpoint {
+    pub id: u32,
+    pub addr: u16,
+    pub bank: Option<usize>,
+    pub condition: Option<Condition>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+//stops after the instruction that touched an address in start..=end, opcode and operand
+//fetches don't count
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub id: u32,
+    pub start: u16,
+    pub end: u16,
+    pub kind: WatchKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BreakReason {
+    Breakpoint(u32),
+    Watchpoint { id: u32, addr: u16, value: u8, write: bool },
+    Step,
+    Paused,
+    Lockup(CpuLockup),
+}
+
+impl fmt::Display for BreakReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BreakReason::Breakpoint(id) => write!(f, "hit breakpoint {}", id),
+            BreakReason::Watchpoint { id, addr, value, write } => write!(
+                f,
+                "watchpoint {}: {} {:#04x} at {:#06x}",
+                id,
+                if *write { "wrote" } else { "read" },
+                value,
+                addr
+            ),
+            BreakReason::Step => write!(f, "step finished"),
+            BreakReason::Paused => write!(f, "paused"),
+            BreakReason::Lockup(lockup) => write!(f, "cpu locked up on {}", lockup),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StepMode {
+    Into(u32),
+    Over { return_addr: u16, sp: u16 },
+    Out { sp: u16 },
+    RunTo(u16),
+}
+
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    next_id: u32,
+    step_mode: Option<StepMode>,
+    //breakpoint at the pc we resume from, so continuing doesn't stop straight away
+    skip_breakpoint_at: Option<u16>,
+    break_reason: Option<BreakReason>,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            next_id: 1,
+            step_mode: None,
+            skip_breakpoint_at: None,
+            break_reason: None,
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16, bank: Option<usize>, condition: Option<Condition>) -> u32 {
+        let id = self.take_id();
+        self.breakpoints.push(Breakpoint { id, addr, bank, condition });
+        id
+    }
+
+    pub fn add_watchpoint(&mut self, start: u16, end: u16, kind: WatchKind) -> u32 {
+        let id = self.take_id();
+        self.watchpoints.push(Watchpoint { id, start: start.min(end), end: start.max(end), kind });
+        id
+    }
+
+    //removes the breakpoint or watchpoint with this id
+    pub fn remove(&mut self, id: u32) -> bool {
+        let count = self.breakpoints.len() + self.watchpoints.len();
+        self.breakpoints.retain(|bp| bp.id != id);
+        self.watchpoints.retain(|wp| wp.id != id);
+        count != self.breakpoints.len() + self.watchpoints.len()
+    }
+
+    pub fn get_breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn get_watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn get_break_reason(&self) -> Option<&BreakReason> {
+        self.break_reason.as_ref()
+    }
+
+    fn take_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    pub(super) fn set_break(&mut self, reason: BreakReason) {
+        self.step_mode = None;
+        self.break_reason = Some(reason);
+    }
+
+    pub(super) fn on_access(&mut self, addr: u16, value: u8, write: bool) {
+        if self.break_reason.is_some() {
+            return;
+        }
+
+        for wp in &self.watchpoints {
+            let kind_matches = match wp.kind {
+                WatchKind::Read => !write,
+                WatchKind::Write => write,
+                WatchKind::Access => true,
+            };
+            if kind_matches && (wp.start..=wp.end).contains(&addr) {
+                self.set_break(BreakReason::Watchpoint { id: wp.id, addr, value, write });
+                return;
+            }
+        }
+    }
+}
+
+//checked before each instruction, true means stay put without running it
+pub(super) fn before_step(gb: &mut Gameboy) -> bool {
+    let pc = gb.pc.0;
+    let bank = gb.rom.rom_bank(pc);
+    let dbg = match gb.debugger.as_mut() {
+        Some(dbg) => dbg,
+        None => return false,
+    };
+
+    if dbg.break_reason.is_some() {
+        return true;
+    }
+    //pc sits on the next instruction the whole time the cpu is halted
+    if gb.other_state.halted {
+        return false;
+    }
+    if dbg.skip_breakpoint_at.take() == Some(pc) {
+        return false;
+    }
+
+    if dbg.step_mode == Some(StepMode::RunTo(pc)) {
+        dbg.set_break(BreakReason::Step);
+        return true;
+    }
+
+    for bp in &dbg.breakpoints {
+        if bp.addr != pc {
+            continue;
+        }
+        if bp.bank.is_some_and(|b| pc < 0x8000 && b != bank) {
+            continue;
+        }
+        if bp.condition.is_some_and(|c| !c.holds(&gb.reg, gb.sp.0, pc)) {
+            continue;
+        }
+        let id = bp.id;
+        dbg.set_break(BreakReason::Breakpoint(id));
+        return true;
+    }
+    false
+}
+
+pub(super) fn after_step(gb: &mut Gameboy, opcode: u8) {
+    let dbg = match gb.debugger.as_mut() {
+        Some(dbg) => dbg,
+        None => return,
+    };
+    if dbg.break_reason.is_some() {
+        return;
+    }
+
+    match dbg.step_mode {
+        Some(StepMode::Into(count)) => {
+            if count <= 1 {
+                dbg.set_break(BreakReason::Step);
+            } else {
+                dbg.step_mode = Some(StepMode::Into(count - 1));
+            }
+        }
+        Some(StepMode::Over { return_addr, sp }) if gb.pc.0 == return_addr && gb.sp.0 >= sp => {
+            dbg.set_break(BreakReason::Step);
+        }
+        //a taken ret leaves sp above where the function started
+        Some(StepMode::Out { sp }) if is_return(opcode) && gb.sp.0 > sp => {
+            dbg.set_break(BreakReason::Step);
+        }
+        _ => {}
+    }
+}
+
+fn is_return(opcode: u8) -> bool {
+    matches!(opcode, 0xc0 | 0xc8 | 0xc9 | 0xd0 | 0xd8 | 0xd9)
+}
+
+fn is_call(opcode: u8) -> bool {
+    matches!(opcode, 0xc4 | 0xcc | 0xcd | 0xd4 | 0xdc) || opcode & 0xc7 == 0xc7
+}
+
+//addresses and values are hex with an optional $ or 0x in front
+fn parse_hex(text: &str) -> Option<u16> {
+    let digits = text.strip_prefix("0x").or_else(|| text.strip_prefix('$')).unwrap_or(text);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+fn parse_watch_kind(text: Option<&str>) -> Option<WatchKind> {
+    match text {
+        None | Some("w") => Some(WatchKind::Write),
+        Some("r") => Some(WatchKind::Read),
+        Some("rw") => Some(WatchKind::Access),
+        _ => None,
+    }
+}
+
+const HELP: &str = "\
+continue, c                 resume
+pause, p                    stop where the cpu is
+step, s [n]                 run n instructions, stepping into calls
+next, n                     run one instruction, stepping over calls
+finish, f                   run until the current function returns
+until, u <addr>|<label>     run until pc reaches addr
+break, b [bank:]<addr>|<label> [if <reg> <op> <value>]
+                            add a breakpoint, op is one of == != < <= > >=
+watch, w <addr>[-<end>]|io [r|w|rw]
+                            add a watchpoint, defaults to writes
+delete, d <id>              remove a breakpoint or watchpoint
+info, i                     list breakpoints and watchpoints
+regs, r                     show registers and the next instruction
+x <addr> [len]              dump memory
+dis [addr] [count]          disassemble
+addresses and values are hex, counts are decimal. labels from a .sym file work as addresses";
+
+impl Gameboy {
+    pub fn enable_debugger(&mut self) {
+        if self.debugger.is_none() {
+            self.debugger = Some(Debugger::new());
+        }
+    }
+
+    pub fn disable_debugger(&mut self) {
+        self.debugger = None;
+    }
+
+    pub fn get_debugger(&self) -> Option<&Debugger> {
+        self.debugger.as_ref()
+    }
+
+    pub fn get_debugger_mut(&mut self) -> Option<&mut Debugger> {
+        self.debugger.as_mut()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.debugger.as_ref().is_some_and(|dbg| dbg.break_reason.is_some())
+    }
+
+    pub fn debug_pause(&mut self) {
+        if let Some(dbg) = &mut self.debugger {
+            if dbg.break_reason.is_none() {
+                dbg.set_break(BreakReason::Paused);
+            }
+        }
+    }
+
+    fn debug_resume(&mut self, step_mode: Option<StepMode>) {
+        let pc = self.pc.0;
+        if let Some(dbg) = &mut self.debugger {
+            dbg.break_reason = None;
+            dbg.step_mode = step_mode;
+            dbg.skip_breakpoint_at = Some(pc);
+        }
+    }
+
+    pub fn debug_continue(&mut self) {
+        self.debug_resume(None);
+    }
+
+    pub fn debug_step_into(&mut self, count: u32) {
+        self.debug_resume(Some(StepMode::Into(count.max(1))));
+    }
+
+    //calls and rsts run to the instruction after them, anything else is a single step
+    pub fn debug_step_over(&mut self) {
+        let instr = self.disassemble_at(self.pc.0);
+        if is_call(instr.bytes[0]) {
+            self.debug_resume(Some(StepMode::Over { return_addr: instr.next_addr(), sp: self.sp.0 }));
+        } else {
+            self.debug_step_into(1);
+        }
+    }
+
+    pub fn debug_step_out(&mut self) {
+        self.debug_resume(Some(StepMode::Out { sp: self.sp.0 }));
+    }
+
+    pub fn debug_run_to(&mut self, addr: u16) {
+        self.debug_resume(Some(StepMode::RunTo(addr)));
+    }
+
+    pub fn register_dump(&self) -> String {
+        let reg = &self.reg;
+        format!(
+            "AF: {:#06x}  BC: {:#06x}  DE: {:#06x}  HL: {:#06x}\nSP: {:#06x}  PC: {:#06x}  IME: {}  Z: {}  N: {}  H: {}  C: {}",
+            reg.get_af(), reg.get_bc(), reg.get_de(), reg.get_hl(), self.sp, self.pc, self.ime as u8,
+            reg.get_flag_z() as u8, reg.get_flag_n() as u8, reg.get_flag_h() as u8, reg.get_flag_c() as u8
+        )
+    }
+
+    //why the debugger stopped, the registers and the instruction about to run
+    pub fn describe_break(&mut self) -> String {
+        let reason = match self.debugger.as_ref().and_then(|dbg| dbg.break_reason.as_ref()) {
+            Some(reason) => reason.to_string(),
+            None => String::from("running"),
+        };
+        let instr = self.disassemble_labelled(self.pc.0);
+        format!("{}\n{}\n{}", reason, self.register_dump(), instr)
+    }
+
+    //runs one line of the text debugger and returns what it printed
+    pub fn debug_command(&mut self, line: &str) -> String {
+        self.enable_debugger();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return String::new(),
+        };
+
+        match command {
+            "help" | "h" | "?" => String::from(HELP),
+            "continue" | "c" => {
+                self.debug_continue();
+                String::new()
+            }
+            "pause" | "p" => {
+                self.debug_pause();
+                self.describe_break()
+            }
+            "step" | "s" => match args.first().map(|n| n.parse::<u32>()) {
+                None => {
+                    self.debug_step_into(1);
+                    String::new()
+                }
+                Some(Ok(count)) => {
+                    self.debug_step_into(count);
+                    String::new()
+                }
+                Some(Err(_)) => String::from("step count must be a decimal number"),
+            },
+            "next" | "n" => {
+                self.debug_step_over();
+                String::new()
+            }
+            "finish" | "f" => {
+                self.debug_step_out();
+                String::new()
+            }
+            "until" | "u" => match args.first().and_then(|a| self.parse_location(a)) {
+                Some((_, addr)) => {
+                    self.debug_run_to(addr);
+                    String::new()
+                }
+                None => String::from("usage: until <addr>"),
+            },
+            "break" | "b" => self.command_break(args),
+            "watch" | "w" => self.command_watch(args),
+            "delete" | "d" => match args.first().and_then(|id| id.parse().ok()) {
+                Some(id) if self.debugger.as_mut().unwrap().remove(id) => format!("deleted {}", id),
+                Some(id) => format!("no breakpoint or watchpoint {}", id),
+                None => String::from("usage: delete <id>"),
+            },
+            "info" | "i" => self.command_info(),
+            "regs" | "r" => {
+                let instr = self.disassemble_labelled(self.pc.0);
+                format!("{}\n{}", self.register_dump(), instr)
+            }
+            "x" => {
+                let addr = match args.first().and_then(|a| self.parse_location(a)) {
+                    Some((_, addr)) => addr,
+                    None => return String::from("usage: x <addr> [len]"),
+                };
+                let length = args.get(1).and_then(|l| l.parse().ok()).unwrap_or(DEFAULT_DUMP_LENGTH);
+                self.dump_memory(addr, length)
+            }
+            "dis" => {
+                let mut addr = args.first().and_then(|a| self.parse_location(a)).map_or(self.pc.0, |(_, addr)| addr);
+                let count = args.get(1).and_then(|c| c.parse().ok()).unwrap_or(DEFAULT_DISASSEMBLE_COUNT);
+                let mut out = Vec::new();
+                for _ in 0..count {
+                    out.push(self.disassemble_labelled(addr));
+                    addr = self.disassemble_at(addr).next_addr();
+                }
+                out.join("\n")
+            }
+            _ => format!("unknown command {}, try help", command),
+        }
+    }
+
+    fn command_break(&mut self, args: &[&str]) -> String {
+        const USAGE: &str = "usage: break [bank:]<addr>|<label> [if <reg> <op> <value>]";
+        let (bank, addr) = match args.first().and_then(|location| self.parse_location(location)) {
+            Some(location) => location,
+            None => return String::from(USAGE),
+        };
+
+        let condition = match &args[1..] {
+            [] => None,
+            ["if", register, op, value] => {
+                match (Register::parse(register), Comparison::parse(op), parse_hex(value)) {
+                    (Some(register), Some(comparison), Some(value)) => Some(Condition { register, comparison, value }),
+                    _ => return String::from(USAGE),
+                }
+            }
+            _ => return String::from(USAGE),
+        };
+
+        let id = self.debugger.as_mut().unwrap().add_breakpoint(addr, bank, condition);
+        format!("breakpoint {} at {:#06x}", id, addr)
+    }
+
+    //a label from the symbol file or [bank:]<hex addr>, labels win when both would parse
+    fn parse_location(&self, text: &str) -> Option<(Option<usize>, u16)> {
+        if let Some(location) = self.resolve_symbol(text) {
+            return Some(location);
+        }
+        match text.split_once(':') {
+            Some((bank, addr)) => Some((Some(parse_hex(bank)? as usize), parse_hex(addr)?)),
+            None => Some((None, parse_hex(text)?)),
+        }
+    }
+
+    fn command_watch(&mut self, args: &[&str]) -> String {
+        const USAGE: &str = "usage: watch <addr>[-<end>]|io [r|w|rw]";
+        let range = match args.first() {
+            Some(&"io") => Some((IO_REG_START, IO_REG_END)),
+            Some(range) => {
+                let addr = |text: &str| self.parse_location(text).map(|(_, addr)| addr);
+                match range.split_once('-') {
+                    Some((start, end)) => addr(start).zip(addr(end)),
+                    None => addr(range).map(|addr| (addr, addr)),
+                }
+            }
+            None => None,
+        };
+        let kind = parse_watch_kind(args.get(1).copied());
+
+        match (range, kind) {
+            (Some((start, end)), Some(kind)) => {
+                let id = self.debugger.as_mut().unwrap().add_watchpoint(start, end, kind);
+                format!("watchpoint {} on {:#06x}-{:#06x} ({:?})", id, start.min(end), start.max(end), kind)
+            }
+            _ => String::from(USAGE),
+        }
+    }
+
+    fn command_info(&self) -> String {
+        let dbg = self.debugger.as_ref().unwrap();
+        let mut out = String::new();
+        for bp in &dbg.breakpoints {
+            let _ = write!(out, "{}: break at ", bp.id);
+            if let Some(bank) = bp.bank {
+                let _ = write!(out, "{:02x}:", bank);
+            }
+            let _ = write!(out, "{:04x}", bp.addr);
+            if let Some(label) = self.get_symbols().and_then(|s| s.symbolize(bp.bank.unwrap_or(0), bp.addr)) {
+                let _ = write!(out, " ({})", label);
+            }
+            if let Some(condition) = bp.condition {
+                let _ = write!(out, " if {}", condition);
+            }
+            out.push('\n');
+        }
+        for wp in &dbg.watchpoints {
+            let _ = writeln!(out, "{}: watch {:04x}-{:04x} ({:?})", wp.id, wp.start, wp.end, wp.kind);
+        }
+        if out.is_empty() {
+            out.push_str("no breakpoints or watchpoints");
+        }
+        out.trim_end().to_string()
+    }
+
+    fn dump_memory(&mut self, addr: u16, length: u16) -> String {
+        let mut lines = Vec::new();
+        let mut line_addr = addr;
+        let end = addr as u32 + length as u32;
+        while (line_addr as u32) < end {
+            let count = (end - line_addr as u32).min(16) as u16;
+            let bytes: Vec<String> = (0..count)
+                .map(|i| format!("{:02x}", self.read_byte_raw(W(line_addr) + W(i)).0))
+                .collect();
+            lines.push(format!("{:04x}  {}", line_addr, bytes.join(" ")));
+            line_addr = line_addr.wrapping_add(16);
+            if line_addr < addr {
+                break;
+            }
+        }
+        lines.join("\n")
+    }
+}
@@ -0,0 +1,164 @@
+use core::num::Wrapping as W;
+use std::fmt;
+
+use super::opcodes::instr_common::{reg_name_high, reg_name_low};
+use super::Gameboy;
+
+const REG_PAIRS: [&str; 4] = ["bc", "de", "hl", "sp"];
+const STACK_REG_PAIRS: [&str; 4] = ["bc", "de", "hl", "af"];
+const CONDITIONS: [&str; 4] = ["nz", "z", "nc", "c"];
+const ALU_OPS: [&str; 8] = ["add a, ", "adc a, ", "sub ", "sbc a, ", "and ", "xor ", "or ", "cp "];
+const CB_SHIFT_OPS: [&str; 8] = ["rlc", "rrc", "rl", "rr", "sla", "sra", "swap", "srl"];
+
+//one decoded instruction, jr targets are resolved to absolute addresses
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+    //address a jump, call, rst or absolute load refers to
+    pub target: Option<u16>,
+}
+
+impl Instruction {
+    pub fn length(&self) -> u16 {
+        self.bytes.len() as u16
+    }
+
+    pub fn next_addr(&self) -> u16 {
+        self.addr.wrapping_add(self.length())
+    }
+}
+
+//address, raw bytes, then the mnemonic
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        write!(f, "{:04x}  {:<8}  {}", self.addr, bytes.join(" "), self.text)
+    }
+}
+
+//length in bytes of the instruction starting with `opcode`, undefined opcodes are one byte
+pub fn instruction_length(opcode: u8) -> u16 {
+    match opcode {
+        0x01 | 0x11 | 0x21 | 0x31 | 0x08 => 3,
+        0xc2 | 0xc3 | 0xca | 0xd2 | 0xda => 3,
+        0xc4 | 0xcc | 0xcd | 0xd4 | 0xdc => 3,
+        0xea | 0xfa => 3,
+        0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 => 2,
+        0xcb | 0xe0 | 0xf0 | 0xe8 | 0xf8 => 2,
+        _ if opcode & 0xc7 == 0x06 || opcode & 0xc7 == 0xc6 => 2,
+        _ => 1,
+    }
+}
+
+//decodes the instruction at the start of `bytes`, which was read from `addr`. bytes past the
+//end of the slice are taken as 0
+pub fn disassemble(bytes: &[u8], addr: u16) -> Instruction {
+    let byte = |i: usize| bytes.get(i).copied().unwrap_or(0);
+    let opcode = byte(0);
+    let length = instruction_length(opcode);
+    let n = byte(1);
+    let nn = u16::from_le_bytes([byte(1), byte(2)]);
+    let relative_target = addr.wrapping_add(2).wrapping_add(n as i8 as u16);
+
+    let op = W(opcode);
+    let pair = REG_PAIRS[(opcode >> 4) as usize & 0x3];
+    let stack_pair = STACK_REG_PAIRS[(opcode >> 4) as usize & 0x3];
+    let condition = CONDITIONS[(opcode >> 3) as usize & 0x3];
+
+    let text = match opcode {
+        0x00 => String::from("nop"),
+        0x01 | 0x11 | 0x21 | 0x31 => format!("ld {}, ${:04x}", pair, nn),
+        0x02 => String::from("ld [bc], a"),
+        0x12 => String::from("ld [de], a"),
+        0x22 => String::from("ld [hl+], a"),
+        0x32 => String::from("ld [hl-], a"),
+        0x0a => String::from("ld a, [bc]"),
+        0x1a => String::from("ld a, [de]"),
+        0x2a => String::from("ld a, [hl+]"),
+        0x3a => String::from("ld a, [hl-]"),
+        0x03 | 0x13 | 0x23 | 0x33 => format!("inc {}", pair),
+        0x0b | 0x1b | 0x2b | 0x3b => format!("dec {}", pair),
+        0x09 | 0x19 | 0x29 | 0x39 => format!("add hl, {}", pair),
+        0x07 => String::from("rlca"),
+        0x0f => String::from("rrca"),
+        0x17 => String::from("rla"),
+        0x1f => String::from("rra"),
+        0x27 => String::from("daa"),
+        0x2f => String::from("cpl"),
+        0x37 => String::from("scf"),
+        0x3f => String::from("ccf"),
+        0x08 => format!("ld [${:04x}], sp", nn),
+        0x10 => String::from("stop"),
+        0x18 => format!("jr ${:04x}", relative_target),
+        0x20 | 0x28 | 0x30 | 0x38 => format!("jr {}, ${:04x}", condition, relative_target),
+        0x76 => String::from("halt"),
+        _ if opcode & 0xc7 == 0x04 => format!("inc {}", reg_name_high(op)),
+        _ if opcode & 0xc7 == 0x05 => format!("dec {}", reg_name_high(op)),
+        _ if opcode & 0xc7 == 0x06 => format!("ld {}, ${:02x}", reg_name_high(op), n),
+        0x40..=0x7f => format!("ld {}, {}", reg_name_high(op), reg_name_low(op)),
+        0x80..=0xbf => format!("{}{}", ALU_OPS[(opcode >> 3) as usize & 0x7], reg_name_low(op)),
+        0xc0 | 0xc8 | 0xd0 | 0xd8 => format!("ret {}", condition),
+        0xc1 | 0xd1 | 0xe1 | 0xf1 => format!("pop {}", stack_pair),
+        0xc5 | 0xd5 | 0xe5 | 0xf5 => format!("push {}", stack_pair),
+        0xc2 | 0xca | 0xd2 | 0xda => format!("jp {}, ${:04x}", condition, nn),
+        0xc3 => format!("jp ${:04x}", nn),
+        0xc4 | 0xcc | 0xd4 | 0xdc => format!("call {}, ${:04x}", condition, nn),
+        0xcd => format!("call ${:04x}", nn),
+        0xc9 => String::from("ret"),
+        0xd9 => String::from("reti"),
+        0xcb => disassemble_cb(n),
+        0xe0 => format!("ldh [${:04x}], a", 0xff00 | n as u16),
+        0xf0 => format!("ldh a, [${:04x}]", 0xff00 | n as u16),
+        0xe2 => String::from("ldh [c], a"),
+        0xf2 => String::from("ldh a, [c]"),
+        0xe8 => format!("add sp, {}", n as i8),
+        0xf8 => format!("ld hl, sp{:+}", n as i8),
+        0xe9 => String::from("jp hl"),
+        0xf9 => String::from("ld sp, hl"),
+        0xea => format!("ld [${:04x}], a", nn),
+        0xfa => format!("ld a, [${:04x}]", nn),
+        0xf3 => String::from("di"),
+        0xfb => String::from("ei"),
+        _ if opcode & 0xc7 == 0xc6 => format!("{}${:02x}", ALU_OPS[(opcode >> 3) as usize & 0x7], n),
+        _ if opcode & 0xc7 == 0xc7 => format!("rst ${:02x}", opcode & 0x38),
+        _ => format!("db ${:02x}", opcode),
+    };
+
+    let target = match opcode {
+        0xc2 | 0xc3 | 0xca | 0xd2 | 0xda => Some(nn),
+        0xc4 | 0xcc | 0xcd | 0xd4 | 0xdc => Some(nn),
+        0x08 | 0xea | 0xfa => Some(nn),
+        0x18 | 0x20 | 0x28 | 0x30 | 0x38 => Some(relative_target),
+        0xe0 | 0xf0 => Some(0xff00 | n as u16),
+        _ if opcode & 0xc7 == 0xc7 => Some((opcode & 0x38) as u16),
+        _ => None,
+    };
+
+    Instruction {
+        addr,
+        bytes: (0..length as usize).map(byte).collect(),
+        text,
+        target,
+    }
+}
+
+fn disassemble_cb(opcode: u8) -> String {
+    let op = W(opcode);
+    let bit = (opcode >> 3) & 0x7;
+    match opcode >> 6 {
+        0 => format!("{} {}", CB_SHIFT_OPS[bit as usize], reg_name_low(op)),
+        1 => format!("bit {}, {}", bit, reg_name_low(op)),
+        2 => format!("res {}, {}", bit, reg_name_low(op)),
+        _ => format!("set {}, {}", bit, reg_name_low(op)),
+    }
+}
+
+impl Gameboy {
+    //decodes the instruction at `addr` as the cpu currently sees memory
+    pub fn disassemble_at(&mut self, addr: u16) -> Instruction {
+        let bytes: Vec<u8> = (0..3).map(|i| self.read_byte_raw(W(addr) + W(i)).0).collect();
+        disassemble(&bytes, addr)
+    }
+}
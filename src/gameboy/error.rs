@@ -0,0 +1,50 @@
+use std::{fmt, io};
+
+#[derive(Debug)]
+pub enum GameboyError {
+    FileRead { path: String, source: io::Error },
+    FileWrite { path: String, source: io::Error },
+    RomTooSmall { size: usize },
+    UnsupportedMapper { header_byte: u8 },
+    BadBootromSize { size: usize },
+    BadSaveState { reason: String },
+    SaveStateVersion { version: u32 },
+}
+
+impl fmt::Display for GameboyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameboyError::FileRead { path, source } => {
+                write!(f, "failed to read {}: {}", path, source)
+            }
+            GameboyError::FileWrite { path, source } => {
+                write!(f, "failed to write {}: {}", path, source)
+            }
+            GameboyError::RomTooSmall { size } => {
+                write!(f, "rom is too small ({:#x} bytes, need at least {:#x})", size, super::game_carts::MIN_ROM_SIZE)
+            }
+            GameboyError::UnsupportedMapper { header_byte } => {
+                write!(f, "unsupported cartridge type {:#04x}, an MBC1 fallback can be enabled explicitly", header_byte)
+            }
+            GameboyError::BadBootromSize { size } => {
+                write!(f, "bootrom must be {:#x} bytes, got {:#x}", super::BOOTROM_SIZE, size)
+            }
+            GameboyError::BadSaveState { reason } => {
+                write!(f, "invalid save state: {}", reason)
+            }
+            GameboyError::SaveStateVersion { version } => {
+                write!(f, "save state version {} is not supported (expected {})", version, super::save_state::SAVE_STATE_VERSION)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GameboyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GameboyError::FileRead { source, .. } => Some(source),
+            GameboyError::FileWrite { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use super::cart_header::CartHeader;
+use super::game_carts::NoMapperCart;
+use super::{Gameboy, SystemType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusAccess {
+    //m-cycle of the step the access happened on, the opcode fetch is cycle 0
+    pub cycle: u32,
+    pub addr: u16,
+    pub value: u8,
+    pub write: bool,
+}
+
+//64k of plain ram standing in for the whole memory map, every timed access the cpu makes
+//is logged so instruction tests can check the bus activity as well as the end state
+pub struct FlatBus {
+    ram: Vec<u8>,
+    accesses: Vec<BusAccess>,
+}
+
+impl FlatBus {
+    fn new() -> FlatBus {
+        FlatBus {
+            ram: vec![0u8; 0x10000],
+            accesses: Vec::new(),
+        }
+    }
+
+    pub(super) fn read(&self, addr: u16) -> u8 {
+        self.ram[addr as usize]
+    }
+
+    pub(super) fn write(&mut self, addr: u16, value: u8) {
+        self.ram[addr as usize] = value;
+    }
+}
+
+//called after a timed read or write, cycles_pending already counts the access
+pub(super) fn log_access(gb: &mut Gameboy, addr: u16, value: u8, write: bool) {
+    let cycle = (gb.cycles_pending / 4).saturating_sub(1);
+    if let Some(bus) = &mut gb.flat_bus {
+        bus.accesses.push(BusAccess { cycle, addr, value, write });
+    }
+}
+
+impl Gameboy {
+    //a cpu wired to 64k of plain ram with nothing mapped, for running instruction tests.
+    //the rest of the hardware still ticks but can't be reached from the bus, so no
+    //interrupts are ever raised
+    pub fn new_flat_bus() -> Gameboy {
+        let rom = Box::new(NoMapperCart::new(Vec::new()));
+        let mut gb = Gameboy::with_cart(SystemType::DMG, rom, CartHeader::rom_only(), Vec::new());
+        gb.other_state.bootrom_enabled = false;
+        gb.flat_bus = Some(FlatBus::new());
+        gb
+    }
+
+    //bus accesses made since the last call, empty unless built with new_flat_bus
+    pub fn take_bus_accesses(&mut self) -> Vec<BusAccess> {
+        match &mut self.flat_bus {
+            Some(bus) => std::mem::take(&mut bus.accesses),
+            None => Vec::new(),
+        }
+    }
+}
@@ -1,25 +1,77 @@
+use crate::gameboy::cart_header::{CartHeader, MapperType};
+use crate::gameboy::error::GameboyError;
+use crate::gameboy::rtc::{Rtc, RtcClock};
+use crate::gameboy::save_state::{StateReader, StateWriter};
 use crate::gameboy::UNDEFINED_READ;
-use log::{debug, trace};
+use log::warn;
 
 const CART_RAM_START: usize = 0xa000;
+pub const MIN_ROM_SIZE: usize = 0x8000;
 
-pub fn get_cart(rom_data: Vec<u8>) -> Box<dyn GameCart> {
-    let header_mapper_byte = rom_data[0x147];
+pub fn get_cart(
+    rom_data: Vec<u8>,
+    header: &CartHeader,
+    allow_unsupported_mapper: bool
+) -> Result<Box<dyn GameCart>, GameboyError> {
+    if rom_data.len() < MIN_ROM_SIZE {
+        return Err(GameboyError::RomTooSmall { size: rom_data.len() });
+    }
+
+    match header.mapper_type() {
+        MapperType::NoMapper => Ok(Box::new(NoMapperCart::new(rom_data))),
+        MapperType::MBC1 => Ok(Box::new(MBC1Cart::new(rom_data, header))),
+        MapperType::MBC2 => Ok(Box::new(MBC2Cart::new(rom_data, header))),
+        MapperType::MBC3 => Ok(Box::new(MBC3Cart::new(rom_data, header))),
+        MapperType::MBC5 => Ok(Box::new(MBC5Cart::new(rom_data, header))),
+        MapperType::Unsupported => {
+            if !allow_unsupported_mapper {
+                return Err(GameboyError::UnsupportedMapper { header_byte: header.cart_type });
+            }
 
-    match header_mapper_byte {
-        0x00 => Box::new(NoMapperCart::new(rom_data)),
-        0x01..=0x03 => Box::new(MBC1Cart::new(rom_data)),
-        0x0F..=0x13 => Box::new(MBC3Cart::new(rom_data)),
-        _ => Box::new(MBC1Cart::new(rom_data))
+            warn!(
+                "Cartridge type {:#04x} ({}) is not supported, falling back to MBC1 as requested",
+                header.cart_type,
+                header.cart_type_name()
+            );
+            Ok(Box::new(MBC1Cart::new(rom_data, header)))
+        }
     }
 }
 
 pub trait GameCart {
     fn read_byte(&self, addr: u16) -> u8;
     fn write_byte(&mut self, addr: u16, val: u8);
+
+    //battery backed data in .sav layout, none if the cart doesn't keep anything
+    fn save_battery(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn load_battery(&mut self, _data: &[u8]) {}
+
+    fn battery_dirty(&self) -> bool {
+        false
+    }
+
+    fn clear_battery_dirty(&mut self) {}
+
+    fn set_rtc_clock(&mut self, _clock: Box<dyn RtcClock>) {}
+
+    fn rumble_active(&self) -> bool {
+        false
+    }
+
+    //rom bank that a read of `addr` currently lands in, 0 for anything outside rom
+    fn rom_bank(&self, addr: u16) -> usize {
+        if (0x4000..0x8000).contains(&addr) { 1 } else { 0 }
+    }
+
+    //mapper registers and cart ram for save states, rom data is never included
+    fn save_state(&self, w: &mut StateWriter);
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), GameboyError>;
 }
 
-struct NoMapperCart {
+pub(super) struct NoMapperCart {
     rom_data: Vec<u8>
 }
 
@@ -34,9 +86,15 @@ impl GameCart for NoMapperCart {
         if addr < 0x8000 { self.rom_data[addr as usize] } else { UNDEFINED_READ }
     }
 
-    fn write_byte(&mut self, addr: u16, val: u8) {
+    fn write_byte(&mut self, _addr: u16, _val: u8) {
         // self.rom_data[addr as usize] = val;
     }
+
+    fn save_state(&self, _w: &mut StateWriter) {}
+
+    fn load_state(&mut self, _r: &mut StateReader) -> Result<(), GameboyError> {
+        Ok(())
+    }
 }
 
 struct MBC1Cart {
@@ -45,31 +103,28 @@ struct MBC1Cart {
     rom_bank_number_5b: u8,
     rom_ram_bank_number_2b: u8,
     rom_data: Vec<u8>,
-    ram_data: Vec<u8>
+    ram_data: Vec<u8>,
+    has_battery: bool,
+    ram_dirty: bool
 }
 
 impl MBC1Cart {
-    pub fn new(rom_data: Vec<u8>) -> MBC1Cart {
-        let ram_size = match rom_data[0x149] {
-            0x00 => 0,
-            0x02 => 0x2000,
-            0x03 => 0x8000,
-            _ => 0
-        };
-
+    pub fn new(rom_data: Vec<u8>, header: &CartHeader) -> MBC1Cart {
         Self {
             ram_enabled: false,
             banking_mode_adv: false,
             rom_bank_number_5b: 0,
             rom_ram_bank_number_2b: 0,
             rom_data,
-            ram_data: vec![0u8; ram_size]
+            ram_data: vec![0u8; header.ram_size().unwrap_or(0)],
+            has_battery: header.has_battery(),
+            ram_dirty: false
         }
     }
 
     fn resolve_addr(&self, addr: u16) -> usize {
         if addr < 0x8000 {
-            let ret_val = if addr < 0x4000 {
+            if addr < 0x4000 {
                 if self.banking_mode_adv {
                     (self.rom_ram_bank_number_2b as usize * 0x80000) + addr as usize 
                 } else {
@@ -77,20 +132,23 @@ impl MBC1Cart {
                 }
             } else {
                 (self.rom_ram_bank_number_2b as usize * 0x80000) + (self.rom_bank_number_5b as usize * 0x4000) + addr as usize - 0x4000
-            };
-            
-            return ret_val;
+            }
+        } else if self.banking_mode_adv {
+            (self.rom_ram_bank_number_2b as usize * 0x4000) + addr as usize - CART_RAM_START
         } else {
-            return if self.banking_mode_adv {
-                (self.rom_ram_bank_number_2b as usize * 0x4000) + addr as usize - CART_RAM_START
-            } else {
-                addr as usize
-            };
+            addr as usize
         }
     }
 }
 
 impl GameCart for MBC1Cart {
+    fn rom_bank(&self, addr: u16) -> usize {
+        if addr >= 0x8000 {
+            return 0;
+        }
+        (self.resolve_addr(addr) % self.rom_data.len()) / 0x4000
+    }
+
     fn read_byte(&self, addr: u16) -> u8 {
         let mapped_addr = self.resolve_addr(addr);
         
@@ -115,11 +173,7 @@ impl GameCart for MBC1Cart {
     fn write_byte(&mut self, addr: u16, val: u8) {
         match addr {
             0x0000..=0x1FFF => {
-                if (val & 0xA) != 0 {
-                    self.ram_enabled = true;
-                } else {
-                    self.ram_enabled = false;
-                }
+                self.ram_enabled = (val & 0xA) != 0;
             }
             0x2000..=0x3FFF => {
                 self.rom_bank_number_5b = val & 0x1F;
@@ -139,6 +193,7 @@ impl GameCart for MBC1Cart {
 
                 if ram_size != 0 && self.ram_enabled {
                     self.ram_data[ram_addr % ram_size] = val;
+                    self.ram_dirty = true;
                 }
             }
             _ => {
@@ -146,31 +201,197 @@ impl GameCart for MBC1Cart {
             }
         }   
     }
+
+    fn save_battery(&self) -> Option<Vec<u8>> {
+        if !self.has_battery || self.ram_data.is_empty() {
+            return None;
+        }
+        Some(self.ram_data.clone())
+    }
+
+    fn load_battery(&mut self, data: &[u8]) {
+        let len = data.len().min(self.ram_data.len());
+        self.ram_data[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn battery_dirty(&self) -> bool {
+        self.has_battery && self.ram_dirty
+    }
+
+    fn clear_battery_dirty(&mut self) {
+        self.ram_dirty = false;
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.ram_enabled);
+        w.write_bool(self.banking_mode_adv);
+        w.write_u8(self.rom_bank_number_5b);
+        w.write_u8(self.rom_ram_bank_number_2b);
+        w.write_bytes(&self.ram_data);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), GameboyError> {
+        self.ram_enabled = r.read_bool()?;
+        self.banking_mode_adv = r.read_bool()?;
+        self.rom_bank_number_5b = r.read_u8()? & 0x1F;
+        self.rom_ram_bank_number_2b = r.read_u8()? & 0x03;
+        r.read_bytes_into(&mut self.ram_data, "mbc1 ram")?;
+        self.ram_dirty = true;
+        Ok(())
+    }
+}
+
+struct MBC2Cart {
+    ram_enabled: bool,
+    rom_bank_number_4b: u8,
+    rom_data: Vec<u8>,
+    ram_data: Vec<u8>,
+    has_battery: bool,
+    ram_dirty: bool
+}
+
+impl MBC2Cart {
+    pub fn new(rom_data: Vec<u8>, header: &CartHeader) -> MBC2Cart {
+        Self {
+            ram_enabled: false,
+            rom_bank_number_4b: 1,
+            rom_data,
+            ram_data: vec![0u8; 0x200],
+            has_battery: header.has_battery(),
+            ram_dirty: false
+        }
+    }
+
+    fn resolve_addr(&self, addr: u16) -> usize {
+        match addr {
+            0x0000..=0x3FFF => {
+                addr as usize
+            },
+            0x4000..=0x7FFF => {
+                (self.rom_bank_number_4b as usize * 0x4000) + addr as usize - 0x4000
+            },
+            _ => {
+                //the 512 half-bytes of built in ram echo across the whole cart ram area
+                (addr as usize - CART_RAM_START) & 0x1FF
+            }
+        }
+    }
+}
+
+impl GameCart for MBC2Cart {
+    fn rom_bank(&self, addr: u16) -> usize {
+        if addr >= 0x8000 {
+            return 0;
+        }
+        (self.resolve_addr(addr) % self.rom_data.len()) / 0x4000
+    }
+
+    fn read_byte(&self, addr: u16) -> u8 {
+        let mapped_addr = self.resolve_addr(addr);
+
+        match addr {
+            0x0000..=0x7FFF => {
+                self.rom_data[mapped_addr % self.rom_data.len()]
+            }
+            0xA000..=0xBFFF => {
+                if !self.ram_enabled {
+                    UNDEFINED_READ
+                } else {
+                    0xF0 | self.ram_data[mapped_addr]
+                }
+            }
+            _ => {
+                panic!("Unimplemented MBC2 read addr 0x{:#04x}!", addr);
+            }
+        }
+    }
+
+    fn write_byte(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x0000..=0x3FFF => {
+                //address bit 8 picks between ram enable and rom bank select
+                if (addr & 0x100) == 0 {
+                    self.ram_enabled = (val & 0x0F) == 0x0A;
+                } else {
+                    self.rom_bank_number_4b = val & 0x0F;
+                    if self.rom_bank_number_4b == 0 {
+                        self.rom_bank_number_4b = 1;
+                    }
+                }
+            }
+            0x4000..=0x7FFF => {}
+            0xA000..=0xBFFF => {
+                if self.ram_enabled {
+                    let ram_addr = self.resolve_addr(addr);
+                    self.ram_data[ram_addr] = val & 0x0F;
+                    self.ram_dirty = true;
+                }
+            }
+            _ => {
+                panic!("Unimplemented MBC2 write 0x{:02x} at 0x{:#04x}!", val, addr);
+            }
+        }
+    }
+
+    fn save_battery(&self) -> Option<Vec<u8>> {
+        if !self.has_battery {
+            return None;
+        }
+        Some(self.ram_data.clone())
+    }
+
+    fn load_battery(&mut self, data: &[u8]) {
+        let len = data.len().min(self.ram_data.len());
+        for (dest, src) in self.ram_data[..len].iter_mut().zip(data.iter()) {
+            *dest = src & 0x0F;
+        }
+    }
+
+    fn battery_dirty(&self) -> bool {
+        self.has_battery && self.ram_dirty
+    }
+
+    fn clear_battery_dirty(&mut self) {
+        self.ram_dirty = false;
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.ram_enabled);
+        w.write_u8(self.rom_bank_number_4b);
+        w.write_bytes(&self.ram_data);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), GameboyError> {
+        self.ram_enabled = r.read_bool()?;
+        self.rom_bank_number_4b = r.read_u8()? & 0x0F;
+        r.read_bytes_into(&mut self.ram_data, "mbc2 ram")?;
+        self.ram_dirty = true;
+        Ok(())
+    }
 }
 
 struct MBC3Cart {
     ram_timer_enabled: bool,
     rom_bank_number_7b: u8,
-    ram_bank_number_2b: u8,
+    ram_rtc_select: u8,
     rom_data: Vec<u8>,
-    ram_data: Vec<u8>
+    ram_data: Vec<u8>,
+    rtc: Option<Rtc>,
+    has_battery: bool,
+    ram_dirty: bool
 }
 
 impl MBC3Cart {
-    pub fn new(rom_data: Vec<u8>) -> MBC3Cart {
-        let ram_size = match rom_data[0x149] {
-            0x00 => 0,
-            0x02 => 0x2000,
-            0x03 => 0x8000,
-            _ => 0
-        };
-
+    pub fn new(rom_data: Vec<u8>, header: &CartHeader) -> MBC3Cart {
         Self {
             ram_timer_enabled: false,
             rom_bank_number_7b: 0,
-            ram_bank_number_2b: 0,
+            ram_rtc_select: 0,
             rom_data,
-            ram_data: vec![0u8; ram_size]
+            ram_data: vec![0u8; header.ram_size().unwrap_or(0)],
+            rtc: if header.has_rtc() { Some(Rtc::new()) } else { None },
+            has_battery: header.has_battery(),
+            ram_dirty: false
         }
     }
 
@@ -183,13 +404,24 @@ impl MBC3Cart {
                 (self.rom_bank_number_7b as usize * 0x4000) + addr as usize - 0x4000
             },
             _ => {
-                (self.ram_bank_number_2b as usize * 0x4000) + addr as usize - CART_RAM_START
+                ((self.ram_rtc_select & 0x03) as usize * 0x2000) + addr as usize - CART_RAM_START
             }
         }
     }
+
+    fn rtc_selected(&self) -> bool {
+        self.rtc.is_some() && Rtc::is_register(self.ram_rtc_select)
+    }
 }
 
 impl GameCart for MBC3Cart {
+    fn rom_bank(&self, addr: u16) -> usize {
+        if addr >= 0x8000 {
+            return 0;
+        }
+        (self.resolve_addr(addr) % self.rom_data.len()) / 0x4000
+    }
+
     fn read_byte(&self, addr: u16) -> u8 {
         let mapped_addr = self.resolve_addr(addr);
         
@@ -199,7 +431,11 @@ impl GameCart for MBC3Cart {
             }
             0xA000..=0xBFFF => {
                 let ram_size = self.ram_data.len();
-                if ram_size == 0 {
+                if !self.ram_timer_enabled {
+                    UNDEFINED_READ
+                } else if self.rtc_selected() {
+                    self.rtc.as_ref().unwrap().read_register(self.ram_rtc_select)
+                } else if ram_size == 0 || self.ram_rtc_select > 0x03 {
                     UNDEFINED_READ
                 } else {
                     self.ram_data[mapped_addr % self.ram_data.len()]
@@ -214,11 +450,7 @@ impl GameCart for MBC3Cart {
     fn write_byte(&mut self, addr: u16, val: u8) {
         match addr {
             0x0000..=0x1FFF => {
-                if (val & 0xA) != 0 {
-                    self.ram_timer_enabled = true;
-                } else {
-                    self.ram_timer_enabled = false;
-                }
+                self.ram_timer_enabled = (val & 0xA) != 0;
             }
             0x2000..=0x3FFF => {
                 self.rom_bank_number_7b = val & 0x7F;
@@ -227,17 +459,27 @@ impl GameCart for MBC3Cart {
                 }
             }
             0x4000..=0x5FFF => {
-                self.ram_bank_number_2b = val & 0x03;
+                self.ram_rtc_select = val & 0x0F;
             }
             0x6000..=0x7FFF => {
-                // TODO timers
+                if let Some(rtc) = &mut self.rtc {
+                    rtc.write_latch(val);
+                }
             }
             0xA000..=0xBFFF => {
                 let ram_addr = self.resolve_addr(addr);
                 let ram_size = self.ram_data.len();
 
-                if ram_size != 0 && self.ram_timer_enabled {
+                if !self.ram_timer_enabled {
+                    return;
+                }
+
+                if self.rtc_selected() {
+                    self.rtc.as_mut().unwrap().write_register(self.ram_rtc_select, val);
+                    self.ram_dirty = true;
+                } else if ram_size != 0 && self.ram_rtc_select <= 0x03 {
                     self.ram_data[ram_addr % ram_size] = val;
+                    self.ram_dirty = true;
                 }
             }
             _ => {
@@ -245,4 +487,213 @@ impl GameCart for MBC3Cart {
             }
         }   
     }
-}
\ No newline at end of file
+
+    fn save_battery(&self) -> Option<Vec<u8>> {
+        if !self.has_battery || (self.ram_data.is_empty() && self.rtc.is_none()) {
+            return None;
+        }
+
+        let mut data = self.ram_data.clone();
+        if let Some(rtc) = &self.rtc {
+            data.extend_from_slice(&rtc.save());
+        }
+        Some(data)
+    }
+
+    fn load_battery(&mut self, data: &[u8]) {
+        let len = data.len().min(self.ram_data.len());
+        self.ram_data[..len].copy_from_slice(&data[..len]);
+
+        if let Some(rtc) = &mut self.rtc {
+            if !rtc.load(&data[len..]) {
+                warn!("Battery save has no valid rtc data, starting the clock from zero");
+            }
+        }
+    }
+
+    fn battery_dirty(&self) -> bool {
+        self.has_battery && self.ram_dirty
+    }
+
+    fn clear_battery_dirty(&mut self) {
+        self.ram_dirty = false;
+    }
+
+    fn set_rtc_clock(&mut self, clock: Box<dyn RtcClock>) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.set_clock(clock);
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.ram_timer_enabled);
+        w.write_u8(self.rom_bank_number_7b);
+        w.write_u8(self.ram_rtc_select);
+        w.write_bytes(&self.ram_data);
+        if let Some(rtc) = &self.rtc {
+            rtc.save_state(w);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), GameboyError> {
+        self.ram_timer_enabled = r.read_bool()?;
+        self.rom_bank_number_7b = r.read_u8()? & 0x7F;
+        self.ram_rtc_select = r.read_u8()? & 0x0F;
+        r.read_bytes_into(&mut self.ram_data, "mbc3 ram")?;
+        if let Some(rtc) = &mut self.rtc {
+            rtc.load_state(r)?;
+        }
+        self.ram_dirty = true;
+        Ok(())
+    }
+}
+
+struct MBC5Cart {
+    ram_enabled: bool,
+    rom_bank_number_9b: u16,
+    ram_bank_number_4b: u8,
+    rom_data: Vec<u8>,
+    ram_data: Vec<u8>,
+    has_rumble: bool,
+    rumble_motor_on: bool,
+    has_battery: bool,
+    ram_dirty: bool
+}
+
+impl MBC5Cart {
+    pub fn new(rom_data: Vec<u8>, header: &CartHeader) -> MBC5Cart {
+        Self {
+            ram_enabled: false,
+            rom_bank_number_9b: 1,
+            ram_bank_number_4b: 0,
+            rom_data,
+            ram_data: vec![0u8; header.ram_size().unwrap_or(0)],
+            has_rumble: header.has_rumble(),
+            rumble_motor_on: false,
+            has_battery: header.has_battery(),
+            ram_dirty: false
+        }
+    }
+
+    fn resolve_addr(&self, addr: u16) -> usize {
+        match addr {
+            0x0000..=0x3FFF => {
+                addr as usize
+            },
+            0x4000..=0x7FFF => {
+                (self.rom_bank_number_9b as usize * 0x4000) + addr as usize - 0x4000
+            },
+            _ => {
+                (self.ram_bank_number_4b as usize * 0x2000) + addr as usize - CART_RAM_START
+            }
+        }
+    }
+}
+
+impl GameCart for MBC5Cart {
+    fn rom_bank(&self, addr: u16) -> usize {
+        if addr >= 0x8000 {
+            return 0;
+        }
+        (self.resolve_addr(addr) % self.rom_data.len()) / 0x4000
+    }
+
+    fn read_byte(&self, addr: u16) -> u8 {
+        let mapped_addr = self.resolve_addr(addr);
+
+        match addr {
+            0x0000..=0x7FFF => {
+                self.rom_data[mapped_addr % self.rom_data.len()]
+            }
+            0xA000..=0xBFFF => {
+                let ram_size = self.ram_data.len();
+                if ram_size == 0 || !self.ram_enabled {
+                    UNDEFINED_READ
+                } else {
+                    self.ram_data[mapped_addr % ram_size]
+                }
+            }
+            _ => {
+                panic!("Unimplemented MBC5 read addr 0x{:#04x}!", addr);
+            }
+        }
+    }
+
+    fn write_byte(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x0000..=0x1FFF => {
+                self.ram_enabled = (val & 0x0F) == 0x0A;
+            }
+            0x2000..=0x2FFF => {
+                self.rom_bank_number_9b = (self.rom_bank_number_9b & 0x100) | val as u16;
+            }
+            0x3000..=0x3FFF => {
+                self.rom_bank_number_9b = (self.rom_bank_number_9b & 0xFF) | ((val as u16 & 0x01) << 8);
+            }
+            0x4000..=0x5FFF => {
+                //rumble carts wire the motor to bit 3, leaving 3 bits of ram bank
+                if self.has_rumble {
+                    self.rumble_motor_on = (val & 0x08) != 0;
+                    self.ram_bank_number_4b = val & 0x07;
+                } else {
+                    self.ram_bank_number_4b = val & 0x0F;
+                }
+            }
+            0x6000..=0x7FFF => {}
+            0xA000..=0xBFFF => {
+                let ram_addr = self.resolve_addr(addr);
+                let ram_size = self.ram_data.len();
+
+                if ram_size != 0 && self.ram_enabled {
+                    self.ram_data[ram_addr % ram_size] = val;
+                    self.ram_dirty = true;
+                }
+            }
+            _ => {
+                panic!("Unimplemented MBC5 write 0x{:02x} at 0x{:#04x}!", val, addr);
+            }
+        }
+    }
+
+    fn save_battery(&self) -> Option<Vec<u8>> {
+        if !self.has_battery || self.ram_data.is_empty() {
+            return None;
+        }
+        Some(self.ram_data.clone())
+    }
+
+    fn load_battery(&mut self, data: &[u8]) {
+        let len = data.len().min(self.ram_data.len());
+        self.ram_data[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn battery_dirty(&self) -> bool {
+        self.has_battery && self.ram_dirty
+    }
+
+    fn clear_battery_dirty(&mut self) {
+        self.ram_dirty = false;
+    }
+
+    fn rumble_active(&self) -> bool {
+        self.has_rumble && self.rumble_motor_on
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.ram_enabled);
+        w.write_u16(self.rom_bank_number_9b);
+        w.write_u8(self.ram_bank_number_4b);
+        w.write_bool(self.rumble_motor_on);
+        w.write_bytes(&self.ram_data);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), GameboyError> {
+        self.ram_enabled = r.read_bool()?;
+        self.rom_bank_number_9b = r.read_u16()? & 0x1FF;
+        self.ram_bank_number_4b = r.read_u8()? & 0x0F;
+        self.rumble_motor_on = r.read_bool()? && self.has_rumble;
+        r.read_bytes_into(&mut self.ram_data, "mbc5 ram")?;
+        self.ram_dirty = true;
+        Ok(())
+    }
+}
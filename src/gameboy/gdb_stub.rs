@@ -0,0 +1,405 @@
+use core::num::Wrapping as W;
+use std::collections::HashMap;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+use log::{info, warn};
+
+use super::debugger::{BreakReason, WatchKind};
+use super::Gameboy;
+
+//registers go over the wire as af, bc, de, hl, sp, pc, 16 bits each, little endian
+const REGISTER_COUNT: usize = 6;
+const MAX_PACKET_SIZE: usize = 0x1000;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.legumegb.sm83.cpu">
+    <reg name="af" bitsize="16" type="int" regnum="0"/>
+    <reg name="bc" bitsize="16" type="int"/>
+    <reg name="de" bitsize="16" type="int"/>
+    <reg name="hl" bitsize="16" type="int"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+//gdb remote serial protocol server on top of the debugger. it never blocks the emulator, the
+//frontend calls poll once a frame and the game keeps running until a client attaches
+pub struct GdbStub {
+    listener: TcpListener,
+    client: Option<GdbClient>,
+}
+
+struct GdbClient {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    last_packet: String,
+    no_ack: bool,
+    //set while the target runs after c or s, the stop reply goes out once it pauses
+    awaiting_stop: bool,
+    //z packets name breakpoints by type, address and length instead of by id
+    points: HashMap<(u8, u16, u16), u32>,
+}
+
+enum ClientState {
+    Connected,
+    Detached,
+}
+
+impl GdbStub {
+    pub fn listen(addr: impl ToSocketAddrs) -> io::Result<GdbStub> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        info!("Waiting for GDB connection on {}", listener.local_addr()?);
+        Ok(GdbStub { listener, client: None })
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    //accepts a client, answers everything it sent since the last call and reports when the
+    //target stopped
+    pub fn poll(&mut self, gb: &mut Gameboy) {
+        if self.client.is_none() {
+            match self.listener.accept() {
+                Ok((stream, peer)) => match GdbClient::new(stream) {
+                    Ok(client) => {
+                        info!("GDB connected from {}", peer);
+                        gb.enable_debugger();
+                        gb.debug_pause();
+                        self.client = Some(client);
+                    }
+                    Err(e) => warn!("Failed to set up GDB connection: {}", e),
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    warn!("Failed to accept GDB connection: {}", e);
+                    return;
+                }
+            }
+        }
+
+        let client = self.client.as_mut().unwrap();
+        let result = client.process(gb).and_then(|state| {
+            if client.awaiting_stop && gb.is_paused() {
+                client.awaiting_stop = false;
+                let reply = stop_reply(gb);
+                client.send_packet(&reply)?;
+            }
+            Ok(state)
+        });
+
+        match result {
+            Ok(ClientState::Connected) => {}
+            Ok(ClientState::Detached) => {
+                info!("GDB detached");
+                self.disconnect(gb);
+            }
+            Err(e) => {
+                warn!("GDB connection lost: {}", e);
+                self.disconnect(gb);
+            }
+        }
+    }
+
+    //drops the client's breakpoints and lets the game run on
+    fn disconnect(&mut self, gb: &mut Gameboy) {
+        if let Some(client) = self.client.take() {
+            if let Some(dbg) = gb.get_debugger_mut() {
+                for id in client.points.values() {
+                    dbg.remove(*id);
+                }
+            }
+            gb.debug_continue();
+        }
+    }
+}
+
+impl GdbClient {
+    fn new(stream: TcpStream) -> io::Result<GdbClient> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(GdbClient {
+            stream,
+            buffer: Vec::new(),
+            last_packet: String::new(),
+            no_ack: false,
+            awaiting_stop: false,
+            points: HashMap::new(),
+        })
+    }
+
+    fn process(&mut self, gb: &mut Gameboy) -> io::Result<ClientState> {
+        let mut chunk = [0u8; 1024];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(io::Error::new(ErrorKind::UnexpectedEof, "client closed the connection")),
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        while let Some(&first) = self.buffer.first() {
+            match first {
+                b'$' => {
+                    let end = match self.buffer.iter().position(|&b| b == b'#') {
+                        Some(end) if self.buffer.len() >= end + 3 => end,
+                        //rest of the packet hasn't arrived yet
+                        _ => break,
+                    };
+                    let raw: Vec<u8> = self.buffer.drain(..end + 3).collect();
+                    let data = &raw[1..end];
+                    let checksum = std::str::from_utf8(&raw[end + 1..]).ok().and_then(|c| u8::from_str_radix(c, 16).ok());
+                    if checksum != Some(packet_checksum(data)) && !self.no_ack {
+                        self.write_raw(b"-")?;
+                        continue;
+                    }
+                    if !self.no_ack {
+                        self.write_raw(b"+")?;
+                    }
+
+                    let packet = String::from_utf8_lossy(data).into_owned();
+                    if let Some(ClientState::Detached) = self.handle_packet(gb, &packet)? {
+                        return Ok(ClientState::Detached);
+                    }
+                }
+                //ctrl-c from the client
+                0x03 => {
+                    self.buffer.remove(0);
+                    gb.debug_pause();
+                    self.awaiting_stop = true;
+                }
+                b'-' => {
+                    self.buffer.remove(0);
+                    let packet = self.last_packet.clone();
+                    self.send_packet(&packet)?;
+                }
+                _ => {
+                    self.buffer.remove(0);
+                }
+            }
+        }
+        Ok(ClientState::Connected)
+    }
+
+    //answers one packet, returns Detached when the client is done with us
+    fn handle_packet(&mut self, gb: &mut Gameboy, packet: &str) -> io::Result<Option<ClientState>> {
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, |c| c.len_utf8()));
+        let reply = match command {
+            "?" => stop_reply(gb),
+            "g" => read_registers(gb).iter().map(|r| hex_le(*r)).collect(),
+            "G" => match parse_hex_bytes(args) {
+                Some(bytes) if bytes.len() == REGISTER_COUNT * 2 => {
+                    for (i, pair) in bytes.chunks(2).enumerate() {
+                        write_register(gb, i, u16::from_le_bytes([pair[0], pair[1]]));
+                    }
+                    String::from("OK")
+                }
+                _ => String::from("E01"),
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(i) if i < REGISTER_COUNT => hex_le(read_registers(gb)[i]),
+                _ => String::from("E01"),
+            },
+            "P" => {
+                let value = args.split_once('=').and_then(|(i, value)| {
+                    let bytes = parse_hex_bytes(value)?;
+                    Some((usize::from_str_radix(i, 16).ok()?, u16::from_le_bytes([*bytes.first()?, *bytes.get(1)?])))
+                });
+                match value {
+                    Some((i, value)) if i < REGISTER_COUNT => {
+                        write_register(gb, i, value);
+                        String::from("OK")
+                    }
+                    _ => String::from("E01"),
+                }
+            }
+            "m" => match parse_addr_len(args) {
+                Some((addr, len)) => (0..len.min(MAX_PACKET_SIZE as u16 / 2))
+                    .map(|i| format!("{:02x}", gb.read_byte_raw(W(addr) + W(i)).0))
+                    .collect(),
+                None => String::from("E01"),
+            },
+            "M" => {
+                let write = args.split_once(':').and_then(|(range, data)| Some((parse_addr_len(range)?, parse_hex_bytes(data)?)));
+                match write {
+                    Some(((addr, len), bytes)) if bytes.len() == len as usize => {
+                        for (i, byte) in bytes.iter().enumerate() {
+                            gb.write_byte_raw(W(addr) + W(i as u16), W(*byte));
+                        }
+                        String::from("OK")
+                    }
+                    _ => String::from("E01"),
+                }
+            }
+            "c" | "s" => {
+                if let Ok(addr) = u16::from_str_radix(args, 16) {
+                    gb.set_pc(addr);
+                }
+                if command == "c" {
+                    gb.debug_continue();
+                } else {
+                    gb.debug_step_into(1);
+                }
+                self.awaiting_stop = true;
+                return Ok(None);
+            }
+            "Z" | "z" => self.handle_point(gb, command == "Z", args),
+            "D" => {
+                self.send_packet("OK")?;
+                return Ok(Some(ClientState::Detached));
+            }
+            "k" => return Ok(Some(ClientState::Detached)),
+            "H" | "T" => String::from("OK"),
+            _ => self.handle_query(packet),
+        };
+
+        self.send_packet(&reply)?;
+        if packet == "QStartNoAckMode" {
+            self.no_ack = true;
+        }
+        Ok(None)
+    }
+
+    fn handle_query(&self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return format!("PacketSize={:x};qXfer:features:read+;QStartNoAckMode+", MAX_PACKET_SIZE);
+        }
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let (offset, length) = match range.split_once(',') {
+                Some((offset, length)) => (usize::from_str_radix(offset, 16), usize::from_str_radix(length, 16)),
+                None => return String::from("E01"),
+            };
+            return match (offset, length) {
+                (Ok(offset), Ok(length)) if offset <= TARGET_XML.len() => {
+                    let end = offset.saturating_add(length).min(TARGET_XML.len());
+                    let prefix = if end == TARGET_XML.len() { 'l' } else { 'm' };
+                    format!("{}{}", prefix, &TARGET_XML[offset..end])
+                }
+                _ => String::from("E01"),
+            };
+        }
+
+        match packet {
+            "QStartNoAckMode" => String::from("OK"),
+            "qAttached" => String::from("1"),
+            "qC" => String::from("QC1"),
+            "qfThreadInfo" => String::from("m1"),
+            "qsThreadInfo" => String::from("l"),
+            //empty reply means not supported
+            _ => String::new(),
+        }
+    }
+
+    //type 0 and 1 are breakpoints, 2 to 4 are write, read and access watchpoints
+    fn handle_point(&mut self, gb: &mut Gameboy, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',');
+        let kind = fields.next().and_then(|k| k.parse::<u8>().ok());
+        let addr = fields.next().and_then(|a| u16::from_str_radix(a, 16).ok());
+        let len = fields.next().and_then(|l| u16::from_str_radix(l, 16).ok());
+        let (kind, addr, len) = match (kind, addr, len) {
+            (Some(kind), Some(addr), Some(len)) if kind <= 4 => (kind, addr, len),
+            _ => return String::new(),
+        };
+
+        gb.enable_debugger();
+        let dbg = gb.get_debugger_mut().unwrap();
+        let key = (kind, addr, len);
+        if !insert {
+            if let Some(id) = self.points.remove(&key) {
+                dbg.remove(id);
+            }
+            return String::from("OK");
+        }
+        if self.points.contains_key(&key) {
+            return String::from("OK");
+        }
+
+        let end = addr.saturating_add(len.max(1) - 1);
+        let id = match kind {
+            0 | 1 => dbg.add_breakpoint(addr, None, None),
+            2 => dbg.add_watchpoint(addr, end, WatchKind::Write),
+            3 => dbg.add_watchpoint(addr, end, WatchKind::Read),
+            _ => dbg.add_watchpoint(addr, end, WatchKind::Access),
+        };
+        self.points.insert(key, id);
+        String::from("OK")
+    }
+
+    fn send_packet(&mut self, data: &str) -> io::Result<()> {
+        self.last_packet = String::from(data);
+        let packet = format!("${}#{:02x}", data, packet_checksum(data.as_bytes()));
+        self.write_raw(packet.as_bytes())
+    }
+
+    //writes block so a full socket buffer can't cut a packet in half
+    fn write_raw(&mut self, data: &[u8]) -> io::Result<()> {
+        self.stream.set_nonblocking(false)?;
+        let result = self.stream.write_all(data).and_then(|_| self.stream.flush());
+        self.stream.set_nonblocking(true)?;
+        result
+    }
+}
+
+fn stop_reply(gb: &Gameboy) -> String {
+    let dbg = match gb.get_debugger() {
+        Some(dbg) => dbg,
+        None => return String::from("S05"),
+    };
+    match dbg.get_break_reason() {
+        Some(BreakReason::Lockup(_)) => String::from("S04"),
+        Some(BreakReason::Watchpoint { id, addr, .. }) => {
+            let kind = dbg.get_watchpoints().iter().find(|wp| wp.id == *id).map(|wp| wp.kind);
+            let name = match kind {
+                Some(WatchKind::Read) => "rwatch",
+                Some(WatchKind::Access) => "awatch",
+                _ => "watch",
+            };
+            format!("T05{}:{:04x};", name, addr)
+        }
+        _ => String::from("S05"),
+    }
+}
+
+fn read_registers(gb: &Gameboy) -> [u16; REGISTER_COUNT] {
+    let reg = gb.get_registers();
+    [reg.get_af().0, reg.get_bc().0, reg.get_de().0, reg.get_hl().0, gb.get_sp(), gb.get_pc()]
+}
+
+fn write_register(gb: &mut Gameboy, index: usize, value: u16) {
+    let reg = gb.get_registers_mut();
+    match index {
+        0 => reg.set_af(W(value)),
+        1 => reg.set_bc(W(value)),
+        2 => reg.set_de(W(value)),
+        3 => reg.set_hl(W(value)),
+        4 => gb.set_sp(value),
+        _ => gb.set_pc(value),
+    }
+}
+
+fn packet_checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+fn hex_le(value: u16) -> String {
+    format!("{:02x}{:02x}", value as u8, value >> 8)
+}
+
+fn parse_hex_bytes(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
+
+fn parse_addr_len(text: &str) -> Option<(u16, u16)> {
+    let (addr, len) = text.split_once(',')?;
+    Some((u16::from_str_radix(addr, 16).ok()?, u16::from_str_radix(len, 16).ok()?))
+}
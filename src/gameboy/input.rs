@@ -0,0 +1,50 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JoypadButton {
+    Start = 0,
+    Select = 1,
+    B = 2,
+    A = 3,
+    Down = 4,
+    Up = 5,
+    Left = 6,
+    Right = 7,
+}
+
+pub const JOYPAD_BUTTON_COUNT: usize = 8;
+
+#[derive(Clone, Copy, Default)]
+pub struct InputKey {
+    state_just_changed: bool,
+    currently_held: bool,
+}
+
+impl InputKey {
+    pub fn new() -> Self {
+        Self {
+            state_just_changed: false,
+            currently_held: false,
+        }
+    }
+
+    pub fn get_held(&self) -> bool {
+        self.currently_held
+    }
+
+    pub fn get_state_just_changed(&self) -> bool {
+        self.state_just_changed
+    }
+
+    pub fn set_held(&mut self, held: bool) {
+        self.state_just_changed = held != self.currently_held;
+        self.currently_held = held;
+    }
+
+    pub fn copy_state_from_other(&mut self, other: &InputKey) {
+        self.currently_held = other.currently_held;
+        self.state_just_changed = other.state_just_changed;
+    }
+}
+
+pub fn new_joypad_keys() -> Vec<InputKey> {
+    vec![InputKey::new(); JOYPAD_BUTTON_COUNT]
+}
@@ -1,16 +1,18 @@
 use core::num::Wrapping as W;
-use std::ops::{BitAnd, Shl};
+use std::ops::Shl;
 
-use super::{Gameboy, UNDEFINED_READ};
-
-use log::debug;
+use super::{timer, Gameboy, UNDEFINED_READ};
 
 const JOYPAD_IO: u8 = 0x00;
+const SERIAL_DATA: u8 = 0x01;
+const SERIAL_CONTROL: u8 = 0x02;
 const COUNTER_DIV: u8 = 0x04;
 const COUNTER_TIMA: u8 = 0x05;
 const COUNTER_TMA: u8 = 0x06;
 const COUNTER_TAC: u8 = 0x07;
 const INT_FLAG: u8 = 0x0f;
+const APU_START: u8 = 0x10;
+const APU_END: u8 = 0x3f;
 const PPU_LCDC: u8 = 0x40;
 const PPU_LCD_STAT: u8 = 0x41;
 const PPU_SCROLL_Y: u8 = 0x42;
@@ -28,53 +30,71 @@ const BOOTROM_DISABLE: u8 = 0x50;
 pub fn read_byte(gb: &mut Gameboy, addr: u8) -> u8 {
     match addr {
         JOYPAD_IO => {
-            return gb.other_state.joypad_io_state;
+            gb.other_state.joypad_io_state
+        }
+        SERIAL_DATA => {
+            gb.serial.read_data()
+        }
+        SERIAL_CONTROL => {
+            gb.serial.read_control()
         }
         COUNTER_DIV => {
-            return (gb.other_state.counter_div >> 8).0 as u8;
+            (gb.other_state.counter_div >> 8).0 as u8
+        }
+        COUNTER_TIMA => {
+            gb.other_state.counter_tima.0
+        }
+        COUNTER_TMA => {
+            gb.other_state.counter_tma
+        }
+        COUNTER_TAC => {
+            timer::read_tac(&gb.other_state)
         }
         INT_FLAG => {
-            return 0b1110_0000 | gb.other_state.int_flag;
+            0b1110_0000 | gb.other_state.int_flag
+        }
+        APU_START..=APU_END => {
+            gb.apu.read_register(addr)
         }
         PPU_LCDC => {
-            return gb.ppu.get_lcdc();
+            gb.ppu.get_lcdc()
         }
         PPU_LCD_STAT => {
-            return gb.ppu.get_stat();
+            gb.ppu.get_stat()
         }
         PPU_SCROLL_Y => {
-            return gb.ppu.scroll_y.0;
+            gb.ppu.scroll_y.0
         }
         PPU_SCROLL_X => {
-            return gb.ppu.scroll_x.0;
+            gb.ppu.scroll_x.0
         }
         PPU_LCD_Y => {
-            return gb.ppu.get_current_y();
+            gb.read_ly()
         }
         PPU_LY_COMPARE => {
-            return gb.ppu.get_ly_compare();
+            gb.ppu.get_ly_compare()
         }
         PPU_BGPAL => {
-            return gb.ppu.get_bgpal();
+            gb.ppu.get_bgpal()
         }
         PPU_OBP1 => {
-            return gb.ppu.get_obp1();
+            gb.ppu.get_obp1()
         }
         PPU_OBP2 => {
-            return gb.ppu.get_obp2();
+            gb.ppu.get_obp2()
         }
         PPU_WX => {
-            return gb.ppu.window_x;
+            gb.ppu.window_x
         }
         PPU_WY => {
-            return gb.ppu.window_y;
+            gb.ppu.window_y
         }
         BOOTROM_DISABLE => {
-            return if gb.other_state.bootrom_enabled { 0 } else { 1 };
+            if gb.other_state.bootrom_enabled { 0 } else { 1 }
         }
         _ => {
             //debug!("Unimplemented IO read from {:#04x}", addr);
-            return UNDEFINED_READ;
+            UNDEFINED_READ
         }
     }
 }
@@ -114,12 +134,30 @@ pub fn write_byte(gb: &mut Gameboy, addr: u8, value: u8) {
 
             gb.other_state.joypad_io_state = new_val;
         }
+        SERIAL_DATA => {
+            gb.serial.write_data(value);
+        }
+        SERIAL_CONTROL => {
+            gb.serial.write_control(value);
+        }
         COUNTER_DIV => {
-            gb.other_state.counter_div = W(0);
+            timer::write_div(&mut gb.other_state);
+        }
+        COUNTER_TIMA => {
+            timer::write_tima(&mut gb.other_state, value);
+        }
+        COUNTER_TMA => {
+            timer::write_tma(&mut gb.other_state, value);
+        }
+        COUNTER_TAC => {
+            timer::write_tac(&mut gb.other_state, value);
         }
         INT_FLAG => {
             gb.other_state.int_flag = value & 0x1f;
         }
+        APU_START..=APU_END => {
+            gb.apu.write_register(addr, value);
+        }
         PPU_LCDC => {
             gb.ppu.set_lcdc(value);
         }
@@ -132,9 +170,7 @@ pub fn write_byte(gb: &mut Gameboy, addr: u8, value: u8) {
         PPU_SCROLL_X => {
             gb.ppu.scroll_x = W(value);
         }
-        PPU_LCD_Y => {
-            return;
-        }
+        PPU_LCD_Y => {}
         PPU_LY_COMPARE => {
             gb.ppu.set_ly_compare(value);
         }
@@ -158,15 +194,12 @@ pub fn write_byte(gb: &mut Gameboy, addr: u8, value: u8) {
         PPU_WX => {
             gb.ppu.window_x = value;
         }
-        BOOTROM_DISABLE => {
-            if value & 0x1 == 0x1 {
-                gb.other_state.bootrom_enabled = false;
-                gb.other_state.instrs_run = 0;
-            }
+        BOOTROM_DISABLE if value & 0x1 == 0x1 => {
+            gb.other_state.bootrom_enabled = false;
+            gb.other_state.instrs_run = 0;
         }
         _ => {
             //debug!("Unimplemented IO write {:#04x} to {:#04x}", value, addr);
-            return;
         }
     }
 }
@@ -0,0 +1,197 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::Path;
+use std::time::Duration;
+
+use log::{info, warn};
+
+use super::serial::LinkEndpoint;
+
+const HANDSHAKE_MAGIC: [u8; 4] = *b"LGBL";
+const PROTOCOL_VERSION: u8 = 1;
+
+//both sides swap a sync message every quantum of emulated cycles and wait for the other
+//one, so neither can run more than a quantum ahead. a transfer is only ever seen by the
+//other side at a quantum boundary, which makes the exchange independent of host timing.
+//a serial byte takes 4096 cycles, so at most one transfer can start per quantum
+const SYNC_QUANTUM_CYCLES: u32 = 2048;
+
+const SYNC_HAS_TRANSFER: u8 = 0x1;
+
+const ROLE_MASTER: u8 = 0;
+const ROLE_SLAVE: u8 = 1;
+
+//a sync is answered within a frame or two unless the other side hung or was paused, in
+//which case the cable is dropped instead of freezing this side too
+const SYNC_TIMEOUT: Duration = Duration::from_secs(5);
+
+trait LinkStream: Read + Write + Send {}
+impl<T: Read + Write + Send> LinkStream for T {}
+
+//one end of a link cable over a tcp or unix domain socket. the listening side is the
+//master and the connecting side the slave, agreed on in the hello. the master opens every
+//sync and the slave only answers, so the lockstep is driven from one side.
+//which side clocks a serial transfer is still up to the games like on hardware, the side
+//whose game selects the internal clock drives that transfer and the other side's armed
+//external clock transfer completes with its byte
+pub struct SocketLink {
+    stream: Option<Box<dyn LinkStream>>,
+    role: u8,
+    cycles_since_sync: u32,
+    peer_data: u8,
+    outgoing: Option<u8>,
+    incoming: Option<u8>,
+}
+
+impl SocketLink {
+    pub fn listen_tcp(addr: impl ToSocketAddrs) -> io::Result<SocketLink> {
+        let listener = TcpListener::bind(addr)?;
+        info!("Waiting for link cable connection on {}", listener.local_addr()?);
+        let (stream, peer) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(SYNC_TIMEOUT))?;
+        info!("Link cable connected to {}", peer);
+        SocketLink::from_stream(Box::new(stream), ROLE_MASTER)
+    }
+
+    pub fn connect_tcp(addr: impl ToSocketAddrs) -> io::Result<SocketLink> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(SYNC_TIMEOUT))?;
+        info!("Link cable connected to {}", stream.peer_addr()?);
+        SocketLink::from_stream(Box::new(stream), ROLE_SLAVE)
+    }
+
+    #[cfg(unix)]
+    pub fn listen_unix(path: &Path) -> io::Result<SocketLink> {
+        //a stale socket file from an earlier run would make bind fail, anything that
+        //isn't a socket is left alone
+        match std::fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", path.display()),
+                ))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        let listener = UnixListener::bind(path)?;
+        info!("Waiting for link cable connection on {}", path.display());
+        let (stream, _) = listener.accept()?;
+        stream.set_read_timeout(Some(SYNC_TIMEOUT))?;
+        info!("Link cable connected");
+        SocketLink::from_stream(Box::new(stream), ROLE_MASTER)
+    }
+
+    #[cfg(unix)]
+    pub fn connect_unix(path: &Path) -> io::Result<SocketLink> {
+        let stream = UnixStream::connect(path)?;
+        stream.set_read_timeout(Some(SYNC_TIMEOUT))?;
+        info!("Link cable connected to {}", path.display());
+        SocketLink::from_stream(Box::new(stream), ROLE_SLAVE)
+    }
+
+    fn from_stream(mut stream: Box<dyn LinkStream>, role: u8) -> io::Result<SocketLink> {
+        let mut hello = [0u8; 10];
+        hello[0..4].copy_from_slice(&HANDSHAKE_MAGIC);
+        hello[4] = PROTOCOL_VERSION;
+        hello[5..9].copy_from_slice(&SYNC_QUANTUM_CYCLES.to_le_bytes());
+        hello[9] = role;
+        stream.write_all(&hello)?;
+
+        let mut peer_hello = [0u8; 10];
+        stream.read_exact(&mut peer_hello)?;
+        if peer_hello[0..9] != hello[0..9] {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "other side is not a compatible link cable",
+            ));
+        }
+        let expected_role = if role == ROLE_MASTER { ROLE_SLAVE } else { ROLE_MASTER };
+        if peer_hello[9] != expected_role {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "both sides of the link cable took the same role",
+            ));
+        }
+
+        Ok(SocketLink {
+            stream: Some(stream),
+            role,
+            cycles_since_sync: 0,
+            peer_data: 0xff,
+            outgoing: None,
+            incoming: None,
+        })
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    fn sync(&mut self, data: u8) {
+        let stream = match &mut self.stream {
+            Some(stream) => stream,
+            None => return,
+        };
+
+        let mut msg = [0u8; 3];
+        msg[1] = data;
+        if let Some(byte) = self.outgoing.take() {
+            msg[0] |= SYNC_HAS_TRANSFER;
+            msg[2] = byte;
+        }
+
+        let mut peer_msg = [0u8; 3];
+        let result = if self.role == ROLE_MASTER {
+            stream.write_all(&msg).and_then(|_| stream.read_exact(&mut peer_msg))
+        } else {
+            stream.read_exact(&mut peer_msg).and_then(|_| stream.write_all(&msg))
+        };
+        if let Err(e) = result {
+            match e.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {
+                    warn!("Link cable disconnected: no sync from the other side in {}s", SYNC_TIMEOUT.as_secs())
+                }
+                _ => warn!("Link cable disconnected: {}", e),
+            }
+            self.stream = None;
+            self.peer_data = 0xff;
+            self.incoming = None;
+            return;
+        }
+
+        self.peer_data = peer_msg[1];
+        //a transfer the other side clocked is only caught if we are armed right now
+        self.incoming = if peer_msg[0] & SYNC_HAS_TRANSFER != 0 { Some(peer_msg[2]) } else { None };
+    }
+}
+
+impl LinkEndpoint for SocketLink {
+    fn exchange_as_master(&mut self, out: u8) -> u8 {
+        if self.stream.is_none() {
+            return 0xff;
+        }
+        self.outgoing = Some(out);
+        self.peer_data
+    }
+
+    fn poll_as_slave(&mut self, _out: u8) -> Option<u8> {
+        self.incoming.take()
+    }
+
+    fn tick(&mut self, cycles: u32, data: u8) {
+        self.cycles_since_sync += cycles;
+        while self.cycles_since_sync >= SYNC_QUANTUM_CYCLES {
+            self.cycles_since_sync -= SYNC_QUANTUM_CYCLES;
+            self.sync(data);
+        }
+    }
+}
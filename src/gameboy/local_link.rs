@@ -0,0 +1,109 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::input::InputKey;
+use super::serial::LinkEndpoint;
+use super::{handle_input, push_rewind_snapshot, step, Gameboy};
+
+#[derive(Default)]
+struct WireSide {
+    data: u8,
+    //byte clocked in by the other side, dropped if we aren't armed by the step after
+    pending: Option<u8>,
+    pending_seen: bool,
+}
+
+#[derive(Default)]
+struct Wire {
+    sides: [WireSide; 2],
+}
+
+//one end of a cable between two gameboys in the same process, only usable on one thread
+pub struct LocalLinkEnd {
+    wire: Rc<RefCell<Wire>>,
+    side: usize,
+}
+
+pub fn local_link_pair() -> (LocalLinkEnd, LocalLinkEnd) {
+    let wire = Rc::new(RefCell::new(Wire::default()));
+    wire.borrow_mut().sides[0].data = 0xff;
+    wire.borrow_mut().sides[1].data = 0xff;
+    (
+        LocalLinkEnd { wire: wire.clone(), side: 0 },
+        LocalLinkEnd { wire, side: 1 },
+    )
+}
+
+impl LinkEndpoint for LocalLinkEnd {
+    fn exchange_as_master(&mut self, out: u8) -> u8 {
+        let mut wire = self.wire.borrow_mut();
+        let other = &mut wire.sides[1 - self.side];
+        other.pending = Some(out);
+        other.pending_seen = false;
+        other.data
+    }
+
+    fn poll_as_slave(&mut self, _out: u8) -> Option<u8> {
+        self.wire.borrow_mut().sides[self.side].pending.take()
+    }
+
+    fn tick(&mut self, _cycles: u32, data: u8) {
+        let mut wire = self.wire.borrow_mut();
+        let side = &mut wire.sides[self.side];
+        side.data = data;
+        if side.pending_seen {
+            side.pending = None;
+        }
+        side.pending_seen = side.pending.is_some();
+    }
+}
+
+//two cores with their serial ports wired together
+pub struct LinkedGameboys {
+    pub first: Gameboy,
+    pub second: Gameboy,
+}
+
+impl LinkedGameboys {
+    pub fn new(mut first: Gameboy, mut second: Gameboy) -> LinkedGameboys {
+        let (first_end, second_end) = local_link_pair();
+        first.set_link_endpoint(Box::new(first_end));
+        second.set_link_endpoint(Box::new(second_end));
+        LinkedGameboys { first, second }
+    }
+}
+
+//runs both cores until each has produced a frame, always stepping whichever is behind so
+//the two stay within one instruction of each other
+pub fn run_linked_frame<'a>(
+    linked: &'a mut LinkedGameboys,
+    first_keys: &[InputKey],
+    second_keys: &[InputKey],
+) -> Result<(Vec<u32>, Vec<u32>), &'a str> {
+    let first = &mut linked.first;
+    let second = &mut linked.second;
+
+    handle_input(first, first_keys);
+    handle_input(second, second_keys);
+    first.display_frame_cycles = 70224;
+    second.display_frame_cycles = 70224;
+
+    let mut first_frame = None;
+    let mut second_frame = None;
+    while first_frame.is_none() || second_frame.is_none() {
+        let step_first = second_frame.is_some()
+            || (first_frame.is_none() && first.cycles_run <= second.cycles_run);
+
+        if step_first {
+            if let Some(frame) = step(first)? {
+                push_rewind_snapshot(first);
+                first_frame = Some(frame);
+            }
+        } else if let Some(frame) = step(second)? {
+            push_rewind_snapshot(second);
+            second_frame = Some(frame);
+        }
+    }
+
+    Ok((first_frame.unwrap(), second_frame.unwrap()))
+}
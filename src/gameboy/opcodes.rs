@@ -59,6 +59,9 @@ pub fn run_opcode(gb: &mut Gameboy, opcode: W<u8>) -> bool {
         0x0f => {
             math_instrs::rrca(gb);
         }
+        0x10 => {
+            misc_instrs::stop(gb);
+        }
         0x11 => {
             load_instrs::ld_de_u16(gb);
         }
@@ -396,5 +399,5 @@ pub fn run_opcode(gb: &mut Gameboy, opcode: W<u8>) -> bool {
             return false;
         }
     }
-    return true;
+    true
 }
@@ -1,32 +1,45 @@
 use crate::gameboy::Gameboy;
 use core::num::Wrapping as W;
 
+//names for the register indexes used by resolve_*_reg_low/high, 6 is the byte at hl
+pub const REG_NAMES: [&str; 8] = ["b", "c", "d", "e", "h", "l", "[hl]", "a"];
+
+#[inline(always)]
+pub fn reg_name_low(opcode: W<u8>) -> &'static str {
+    REG_NAMES[(opcode.0 & 0x7) as usize]
+}
+
+#[inline(always)]
+pub fn reg_name_high(opcode: W<u8>) -> &'static str {
+    REG_NAMES[((opcode >> 3).0 & 0x7) as usize]
+}
+
 #[inline(always)]
 pub fn resolve_read_reg_low(gb: &mut Gameboy, opcode: W<u8>) -> W<u8> {
     match opcode.0 & 0x7 {
         0x0 => {
-            return gb.reg.b;
+            gb.reg.b
         }
         0x1 => {
-            return gb.reg.c;
+            gb.reg.c
         }
         0x2 => {
-            return gb.reg.d;
+            gb.reg.d
         }
         0x3 => {
-            return gb.reg.e;
+            gb.reg.e
         }
         0x4 => {
-            return gb.reg.h;
+            gb.reg.h
         }
         0x5 => {
-            return gb.reg.l;
+            gb.reg.l
         }
         0x6 => {
-            return gb.read_byte(gb.reg.get_hl());
+            gb.read_byte(gb.reg.get_hl())
         }
         0x7 => {
-            return gb.reg.a;
+            gb.reg.a
         }
         _ => {
             panic!("impossible???");
@@ -38,28 +51,28 @@ pub fn resolve_read_reg_low(gb: &mut Gameboy, opcode: W<u8>) -> W<u8> {
 pub fn resolve_read_reg_high(gb: &mut Gameboy, opcode: W<u8>) -> W<u8> {
     match (opcode >> 3).0 & 0x7 {
         0x0 => {
-            return gb.reg.b;
+            gb.reg.b
         }
         0x1 => {
-            return gb.reg.c;
+            gb.reg.c
         }
         0x2 => {
-            return gb.reg.d;
+            gb.reg.d
         }
         0x3 => {
-            return gb.reg.e;
+            gb.reg.e
         }
         0x4 => {
-            return gb.reg.h;
+            gb.reg.h
         }
         0x5 => {
-            return gb.reg.l;
+            gb.reg.l
         }
         0x6 => {
-            return gb.read_byte(gb.reg.get_hl());
+            gb.read_byte(gb.reg.get_hl())
         }
         0x7 => {
-            return gb.reg.a;
+            gb.reg.a
         }
         _ => {
             panic!("impossible???");
@@ -89,7 +102,7 @@ pub fn resolve_write_reg_low(gb: &mut Gameboy, opcode: W<u8>, value: W<u8>) {
             gb.reg.l = value;
         }
         0x6 => {
-            return gb.write_byte(gb.reg.get_hl(), value);
+            gb.write_byte(gb.reg.get_hl(), value);
         }
         0x7 => {
             gb.reg.a = value;
@@ -122,7 +135,7 @@ pub fn resolve_write_reg_high(gb: &mut Gameboy, opcode: W<u8>, value: W<u8>) {
             gb.reg.l = value;
         }
         0x6 => {
-            return gb.write_byte(gb.reg.get_hl(), value);
+            gb.write_byte(gb.reg.get_hl(), value);
         }
         0x7 => {
             gb.reg.a = value;
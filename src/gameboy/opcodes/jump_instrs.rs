@@ -1,8 +1,6 @@
-use crate::gameboy::Gameboy;
+use crate::gameboy::{profiler, Gameboy};
 use core::num::Wrapping as W;
 
-use log::debug;
-
 #[inline(always)]
 pub fn jr_nz_i8(gb: &mut Gameboy) {
     let offset = W(gb.read_byte_inc_pc().0 as i8 as u16);
@@ -52,6 +50,7 @@ pub fn call_u16(gb: &mut Gameboy) {
     gb.push_short(gb.pc);
     gb.pc = address;
     gb.cycles_pending += 4;
+    profiler::on_call(gb);
 }
 
 #[inline(always)]
@@ -61,6 +60,7 @@ pub fn call_nz_u16(gb: &mut Gameboy) {
         gb.push_short(gb.pc);
         gb.pc = address;
         gb.cycles_pending += 4;
+        profiler::on_call(gb);
     }
 }
 
@@ -71,6 +71,7 @@ pub fn call_nc_u16(gb: &mut Gameboy) {
         gb.push_short(gb.pc);
         gb.pc = address;
         gb.cycles_pending += 4;
+        profiler::on_call(gb);
     }
 }
 
@@ -81,6 +82,7 @@ pub fn call_z_u16(gb: &mut Gameboy) {
         gb.push_short(gb.pc);
         gb.pc = address;
         gb.cycles_pending += 4;
+        profiler::on_call(gb);
     }
 }
 
@@ -91,6 +93,7 @@ pub fn call_c_u16(gb: &mut Gameboy) {
         gb.push_short(gb.pc);
         gb.pc = address;
         gb.cycles_pending += 4;
+        profiler::on_call(gb);
     }
 }
 
@@ -98,6 +101,7 @@ pub fn call_c_u16(gb: &mut Gameboy) {
 pub fn ret(gb: &mut Gameboy) {
     gb.pc = gb.pop_short();
     gb.cycles_pending += 4;
+    profiler::on_return(gb);
 }
 
 #[inline(always)]
@@ -105,6 +109,7 @@ pub fn ret_nz(gb: &mut Gameboy) {
     gb.cycles_pending += 4;
     if !gb.reg.get_flag_z() {
         gb.pc = gb.pop_short();
+        profiler::on_return(gb);
     }
 }
 
@@ -113,6 +118,7 @@ pub fn ret_nc(gb: &mut Gameboy) {
     gb.cycles_pending += 4;
     if !gb.reg.get_flag_c() {
         gb.pc = gb.pop_short();
+        profiler::on_return(gb);
     }
 }
 
@@ -121,6 +127,7 @@ pub fn ret_z(gb: &mut Gameboy) {
     gb.cycles_pending += 4;
     if gb.reg.get_flag_z() {
         gb.pc = gb.pop_short();
+        profiler::on_return(gb);
     }
 }
 
@@ -129,6 +136,7 @@ pub fn ret_c(gb: &mut Gameboy) {
     gb.cycles_pending += 4;
     if gb.reg.get_flag_c() {
         gb.pc = gb.pop_short();
+        profiler::on_return(gb);
     }
 }
 
@@ -137,6 +145,7 @@ pub fn reti(gb: &mut Gameboy) {
     gb.pc = gb.pop_short();
     gb.ime = true;
     gb.cycles_pending += 4;
+    profiler::on_return(gb);
 }
 
 #[inline(always)]
@@ -145,6 +154,7 @@ pub fn rst_n(gb: &mut Gameboy, opcode: W<u8>) {
     gb.push_short(gb.pc);
     gb.pc = W(jump_addr as u16);
     gb.cycles_pending += 4;
+    profiler::on_call(gb);
 }
 
 #[inline(always)]
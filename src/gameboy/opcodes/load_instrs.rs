@@ -1,5 +1,3 @@
-use log::debug;
-
 use crate::gameboy::Gameboy;
 use core::num::Wrapping as W;
 
@@ -389,7 +389,7 @@ pub fn add_hl_sp(gb: &mut Gameboy) {
 pub fn rla(gb: &mut Gameboy) {
     let lhs = gb.reg.a;
     let old_carry = W(gb.reg.get_flag_c() as u8);
-    let bit_7 = if (lhs.0 & 0x80) != 0 { true } else { false };
+    let bit_7 = (lhs.0 & 0x80) != 0;
     let result = (lhs << 1) | old_carry;
     gb.reg.unset_all_flags();
 
@@ -410,7 +410,7 @@ pub fn rra(gb: &mut Gameboy) {
     } else {
         W(0x00u8)
     };
-    let bit_0 = if (lhs.0 & 0x1) != 0 { true } else { false };
+    let bit_0 = (lhs.0 & 0x1) != 0;
     let result = (lhs >> 1) | old_carry;
     gb.reg.unset_all_flags();
 
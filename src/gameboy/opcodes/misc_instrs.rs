@@ -1,6 +1,4 @@
-use crate::gameboy::Gameboy;
-
-use log::debug;
+use crate::gameboy::{timer, Gameboy};
 
 #[inline(always)]
 pub fn di(gb: &mut Gameboy) {
@@ -12,9 +10,28 @@ pub fn ei(gb: &mut Gameboy) {
     gb.other_state.ime_next_cycle = true;
 }
 
+//with an interrupt already pending halt never actually halts. with ime off the halt bug
+//kicks in and the next byte is read twice, with ime only just turned on by an ei the
+//interrupt returns to the halt itself
 #[inline(always)]
 pub fn halt(gb: &mut Gameboy) {
-    gb.other_state.halted = true;
+    let interrupt_pending = gb.other_state.int_enable & gb.other_state.int_flag & 0x1f != 0;
+    if !interrupt_pending {
+        gb.other_state.halted = true;
+    } else if !gb.ime {
+        gb.other_state.halt_bug = true;
+    } else if gb.other_state.ime_just_enabled {
+        gb.pc -= 1;
+    }
+}
+
+//the byte after stop is skipped. div is reset and the system clock stops until a
+//button is pressed
+#[inline(always)]
+pub fn stop(gb: &mut Gameboy) {
+    gb.pc += 1;
+    timer::write_div(&mut gb.other_state);
+    gb.other_state.stopped = true;
 }
 
 #[inline(always)]
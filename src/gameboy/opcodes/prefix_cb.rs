@@ -3,8 +3,6 @@ use core::num::Wrapping as W;
 
 use super::instr_common;
 
-use log::error;
-
 #[inline(always)]
 pub fn prefix_cb(gb: &mut Gameboy) -> bool {
     let opcode = gb.read_byte_inc_pc();
@@ -45,7 +43,7 @@ pub fn prefix_cb(gb: &mut Gameboy) -> bool {
         }
     }
 
-    return true;
+    true
 }
 
 #[inline(always)]
@@ -151,7 +149,7 @@ fn bit_n_reg(gb: &mut Gameboy, opcode: W<u8>) {
 fn rl_reg(gb: &mut Gameboy, opcode: W<u8>) {
     let lhs = instr_common::resolve_read_reg_low(gb, opcode);
     let old_carry = W(gb.reg.get_flag_c() as u8);
-    let bit_7 = if (lhs.0 & 0x80) != 0 { true } else { false };
+    let bit_7 = (lhs.0 & 0x80) != 0;
     let result = (lhs << 1) | old_carry;
     gb.reg.unset_all_flags();
 
@@ -178,7 +176,7 @@ fn rr_reg(gb: &mut Gameboy, opcode: W<u8>) {
     } else {
         W(0x00u8)
     };
-    let bit_0 = if (lhs.0 & 0x1) != 0 { true } else { false };
+    let bit_0 = (lhs.0 & 0x1) != 0;
     let result = (lhs >> 1) | old_carry;
 
     gb.reg.unset_all_flags();
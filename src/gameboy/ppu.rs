@@ -2,8 +2,9 @@ use core::num::Wrapping as W;
 
 use super::{OtherState, SystemType, INT_STAT, INT_VBLANK};
 use super::banked_memory::BankedMemory;
+use super::error::GameboyError;
+use super::save_state::{StateReader, StateWriter};
 
-use log::debug;
 
 #[derive(Clone, Copy, Debug)]
 pub enum Color {
@@ -13,6 +14,18 @@ pub enum Color {
     White = 0x00FFFFFF
 }
 
+impl Color {
+    //2 bit shade as written to BGP/OBP0/OBP1
+    fn palette_index(self) -> u8 {
+        match self {
+            Color::White => 0,
+            Color::LGray => 1,
+            Color::DGray => 2,
+            Color::Black => 3,
+        }
+    }
+}
+
 #[derive(PartialEq, Clone, Copy)]
 enum PPUMode {
     HBlank = 0,
@@ -22,8 +35,8 @@ enum PPUMode {
 }
 
 const COLORS: [Color; 4] = [Color::White, Color::LGray, Color::DGray, Color::Black];
-const GB_SCREEN_WIDTH: usize = 160;
-const GB_SCREEN_HEIGHT: usize = 144;
+pub const GB_SCREEN_WIDTH: usize = 160;
+pub const GB_SCREEN_HEIGHT: usize = 144;
 const VBLANK_LINES: usize = 10;
 const OAM_SCAN_DOTS: u16 = 80;
 const PIXEL_PUT_MIN_DOTS: u16 = 172;
@@ -140,7 +153,7 @@ impl PPU {
         val |= (self.bg_tilemap_offset as u8) << 3;
         val |= (self.obj_size_is_8x16 as u8) << 2;
         val |= (self.obj_enable as u8) << 1;
-        val |= (self.bg_window_priority as u8) << 0;
+        val |= self.bg_window_priority as u8;
         val
     }
 
@@ -160,24 +173,24 @@ impl PPU {
     }
 
     pub fn get_bgpal(&self) -> u8 {
-        let mut val = self.bg_colors[0] as u8;
-        val |= (self.bg_colors[1] as u8).wrapping_shl(2);
-        val |= (self.bg_colors[2] as u8).wrapping_shl(4);
-        val | (self.bg_colors[3] as u8).wrapping_shl(6)
+        let mut val = self.bg_colors[0].palette_index();
+        val |= self.bg_colors[1].palette_index().wrapping_shl(2);
+        val |= self.bg_colors[2].palette_index().wrapping_shl(4);
+        val | self.bg_colors[3].palette_index().wrapping_shl(6)
     }
 
     pub fn get_obp1(&self) -> u8 {
-        let mut val = self.obp1_colors[0] as u8;
-        val |= (self.obp1_colors[1] as u8).wrapping_shl(2);
-        val |= (self.obp1_colors[2] as u8).wrapping_shl(4);
-        val | (self.obp1_colors[3] as u8).wrapping_shl(6)
+        let mut val = self.obp1_colors[0].palette_index();
+        val |= self.obp1_colors[1].palette_index().wrapping_shl(2);
+        val |= self.obp1_colors[2].palette_index().wrapping_shl(4);
+        val | self.obp1_colors[3].palette_index().wrapping_shl(6)
     }
 
     pub fn get_obp2(&self) -> u8 {
-        let mut val = self.obp2_colors[0] as u8;
-        val |= (self.obp2_colors[1] as u8).wrapping_shl(2);
-        val |= (self.obp2_colors[2] as u8).wrapping_shl(4);
-        val | (self.obp2_colors[3] as u8).wrapping_shl(6)
+        let mut val = self.obp2_colors[0].palette_index();
+        val |= self.obp2_colors[1].palette_index().wrapping_shl(2);
+        val |= self.obp2_colors[2].palette_index().wrapping_shl(4);
+        val | self.obp2_colors[3].palette_index().wrapping_shl(6)
     }
 
     pub fn set_bgpal(&mut self, value: u8) {
@@ -218,7 +231,7 @@ impl PPU {
     }
 
     pub fn get_ly_compare(&self) -> u8 {
-        return self.ly_compare;
+        self.ly_compare
     }
 
     pub fn set_ly_compare(&mut self, value: u8) {
@@ -229,7 +242,7 @@ impl PPU {
         if self.current_mode != PPUMode::PixelPut {
             return self.vram.read_byte(addr);
         }
-        return super::UNDEFINED_READ;
+        super::UNDEFINED_READ
     }
 
     pub fn write_vram_byte(&mut self, addr: u16, value: u8) {
@@ -242,7 +255,7 @@ impl PPU {
         if (self.current_mode != PPUMode::PixelPut) && (self.current_mode != PPUMode::OAMScan) {
             return self.oam[addr as usize];
         }
-        return super::UNDEFINED_READ;
+        super::UNDEFINED_READ
     }
 
     pub fn write_oam_byte(&mut self, addr: u16, value: u8) {
@@ -256,24 +269,20 @@ impl PPU {
         let data_2 = self.vram.read_byte(tile_addr.wrapping_add(1));
         let mut ret_pixels: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 0];
 
-        for pixel in 0..8 {
+        for (pixel, ret_pixel) in ret_pixels.iter_mut().enumerate() {
             let bitmask = 1 << (7 - pixel);
             let pixel_raw_color_1 = if data_1 & bitmask != 0 { 1 } else { 0 };
             let pixel_raw_color_2 = if data_2 & bitmask != 0 { 2 } else { 0 };
             let pixel_raw_color = pixel_raw_color_1 | pixel_raw_color_2;
-            ret_pixels[pixel] = pixel_raw_color;
+            *ret_pixel = pixel_raw_color;
         }
 
-        return ret_pixels;
+        ret_pixels
     }
 
     fn render_tiles(&mut self, render_window: bool) {
         let start_x;
-        let end_x;
-        let x_scroll_tile_offset;
         let current_y;
-        let y_tile;
-        let y_in_tile;
         let mut line_tilemap_offset;
 
         let this_line_screen_offset;
@@ -296,15 +305,15 @@ impl PPU {
             screen_put_offset = this_line_screen_offset;
         }
 
-        end_x = start_x as u16 + GB_SCREEN_WIDTH as u16 + 8;
-        x_scroll_tile_offset = start_x % 8;
-        y_tile = W((current_y >> 3).0 as u16);
-        y_in_tile = current_y % W(8);
+        let end_x = start_x as u16 + GB_SCREEN_WIDTH as u16 + 8;
+        let x_scroll_tile_offset = start_x % 8;
+        let y_tile = W((current_y >> 3).0 as u16);
+        let y_in_tile = current_y % W(8);
         line_tilemap_offset = (y_tile * W(32)) + W(0x1800);
 
-        if (render_window && self.window_tilemap_offset) ^ self.dbg_tilemap_win_swap {
-            line_tilemap_offset += 0x400;
-        } else if (!render_window && self.bg_tilemap_offset) ^ self.dbg_tilemap_bg_swap {
+        if ((render_window && self.window_tilemap_offset) ^ self.dbg_tilemap_win_swap)
+            || ((!render_window && self.bg_tilemap_offset) ^ self.dbg_tilemap_bg_swap)
+        {
             line_tilemap_offset += 0x400;
         }
 
@@ -340,7 +349,7 @@ impl PPU {
 
                 if (screen_address < 23040)
                     && (screen_address >= (this_line_screen_offset as usize))
-                    && (screen_address < ((this_line_screen_offset as usize + GB_SCREEN_WIDTH) as usize))
+                    && (screen_address < (this_line_screen_offset as usize + GB_SCREEN_WIDTH))
                 {
                     if self.bg_window_priority {
                         self.screen[screen_address] = screen_color;
@@ -367,7 +376,7 @@ impl PPU {
             let sprite_tileidx = self.oam[sprite_idx + 2];
             let sprite_attribs = self.oam[sprite_idx + 3];
 
-            let sprite_priority = sprite_attribs & 0x80 != 0;
+            let _sprite_priority = sprite_attribs & 0x80 != 0;
             let y_flip = sprite_attribs & 0x40 != 0;
             let x_flip = sprite_attribs & 0x20 != 0;
             let sprite_pal = sprite_attribs & 0x10 != 0;
@@ -452,10 +461,8 @@ impl PPU {
                             .wrapping_sub((HBLANK_MAX_DOTS - self.mode_3_extra_dots) as u64);
                         self.current_y += 1;
 
-                        if self.current_y.0 == self.ly_compare {
-                            if self.ly_stat_int {
-                                other_state.int_flag |= INT_STAT;
-                            }
+                        if self.current_y.0 == self.ly_compare && self.ly_stat_int {
+                            other_state.int_flag |= INT_STAT;
                         }
 
                         if self.current_y.0 >= GB_SCREEN_HEIGHT as u8 {
@@ -482,10 +489,8 @@ impl PPU {
                             .wrapping_sub(LINE_TOTAL_DOTS as u64);
                         self.current_y += 1;
 
-                        if self.current_y.0 == self.ly_compare {
-                            if self.ly_stat_int {
-                                other_state.int_flag |= INT_STAT;
-                            }
+                        if self.current_y.0 == self.ly_compare && self.ly_stat_int {
+                            other_state.int_flag |= INT_STAT;
                         }
 
                         if self.current_y.0 >= (GB_SCREEN_HEIGHT + VBLANK_LINES) as u8 {
@@ -493,10 +498,8 @@ impl PPU {
                             self.current_y = W(0);
                             self.current_x = W(0);
 
-                            if self.current_y.0 == self.ly_compare {
-                                if self.ly_stat_int {
-                                    other_state.int_flag |= INT_STAT;
-                                }
+                            if self.current_y.0 == self.ly_compare && self.ly_stat_int {
+                                other_state.int_flag |= INT_STAT;
                             }
 
                             if self.oam_stat_int {
@@ -537,10 +540,75 @@ impl PPU {
                 }
             }
         }
-        return None;
+        None
     }
 
     pub fn is_enabled(&self) -> bool {
         self.ppu_enabled
     }
+
+    pub fn get_screen(&self) -> &[u32] {
+        &self.screen
+    }
+
+    //debug toggles are frontend settings, so they are left out
+    pub fn save_state(&self, w: &mut StateWriter) {
+        self.vram.save_state(w);
+        w.write_bytes(&self.oam);
+        w.write_u32(self.screen.len() as u32);
+        for pixel in &self.screen {
+            w.write_u32(*pixel);
+        }
+
+        w.write_u8(self.get_lcdc());
+        w.write_u8(self.get_stat());
+        w.write_u8(self.scroll_x.0);
+        w.write_u8(self.scroll_y.0);
+        w.write_u8(self.current_x.0);
+        w.write_u8(self.current_y.0);
+        w.write_u8(self.current_window_y.0);
+        w.write_u8(self.get_bgpal());
+        w.write_u8(self.get_obp1());
+        w.write_u8(self.get_obp2());
+        w.write_u64(self.current_mode_cycles);
+        w.write_u16(self.mode_3_extra_dots);
+        w.write_u8(self.ly_compare);
+        w.write_u8(self.window_x);
+        w.write_u8(self.window_y);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), GameboyError> {
+        self.vram.load_state(r)?;
+        r.read_bytes_into(&mut self.oam, "oam")?;
+        if r.read_u32()? as usize != self.screen.len() {
+            return Err(GameboyError::BadSaveState { reason: String::from("screen size mismatch") });
+        }
+        for pixel in self.screen.iter_mut() {
+            *pixel = r.read_u32()?;
+        }
+
+        self.set_lcdc(r.read_u8()?);
+        let stat = r.read_u8()?;
+        self.set_stat(stat);
+        self.current_mode = match stat & 0x3 {
+            0 => PPUMode::HBlank,
+            1 => PPUMode::VBlank,
+            2 => PPUMode::OAMScan,
+            _ => PPUMode::PixelPut,
+        };
+        self.scroll_x = W(r.read_u8()?);
+        self.scroll_y = W(r.read_u8()?);
+        self.current_x = W(r.read_u8()?);
+        self.current_y = W(r.read_u8()?);
+        self.current_window_y = W(r.read_u8()?);
+        self.set_bgpal(r.read_u8()?);
+        self.set_obp1(r.read_u8()?);
+        self.set_obp2(r.read_u8()?);
+        self.current_mode_cycles = r.read_u64()?;
+        self.mode_3_extra_dots = r.read_u16()?;
+        self.ly_compare = r.read_u8()?;
+        self.window_x = r.read_u8()?;
+        self.window_y = r.read_u8()?;
+        Ok(())
+    }
 }
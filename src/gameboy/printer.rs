@@ -0,0 +1,311 @@
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use log::{error, info, warn};
+
+use super::error::GameboyError;
+use super::serial::LinkEndpoint;
+
+const MAGIC_1: u8 = 0x88;
+const MAGIC_2: u8 = 0x33;
+
+const CMD_INIT: u8 = 0x01;
+const CMD_PRINT: u8 = 0x02;
+const CMD_DATA: u8 = 0x04;
+const CMD_STATUS: u8 = 0x0f;
+
+const STATUS_CHECKSUM_ERROR: u8 = 0x01;
+const STATUS_PRINTING: u8 = 0x02;
+const STATUS_IMAGE_FULL: u8 = 0x04;
+const STATUS_UNPROCESSED: u8 = 0x08;
+const STATUS_PACKET_ERROR: u8 = 0x10;
+
+//sent back in the first of the two reply bytes at the end of every packet
+const PRINTER_ID: u8 = 0x81;
+
+const TILE_BYTES: usize = 16;
+const TILES_PER_ROW: usize = 20;
+const PRINT_WIDTH: usize = TILES_PER_ROW * 8;
+const MAX_IMAGE_BYTES: usize = 0x2000;
+//each margin unit is taken as one tile row of blank paper
+const MARGIN_UNIT_LINES: usize = 8;
+//how many packets report the printer as busy after a print, games poll status until it clears
+const PRINT_BUSY_PACKETS: u8 = 4;
+
+const SHADES: [u8; 4] = [0xff, 0xaa, 0x55, 0x00];
+//a palette of 0 is treated by the printer as the default 0xe4 mapping
+const DEFAULT_PALETTE: u8 = 0xe4;
+
+#[derive(PartialEq, Clone, Copy)]
+enum PacketState {
+    Magic1,
+    Magic2,
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    Ack,
+    Status,
+}
+
+//game boy printer on the end of the link cable, each printed strip is written out as a png
+pub struct Printer {
+    output_dir: PathBuf,
+    next_print_id: u32,
+    printed_files: Vec<PathBuf>,
+
+    state: PacketState,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    packet_data: Vec<u8>,
+    checksum: u16,
+    computed_checksum: u16,
+
+    image_data: Vec<u8>,
+    status: u8,
+    busy_packets: u8,
+}
+
+impl Printer {
+    //the output directory is created up front so a bad path shows up before anything is printed
+    pub fn new(output_dir: impl Into<PathBuf>) -> Result<Printer, GameboyError> {
+        let output_dir = output_dir.into();
+        fs::create_dir_all(&output_dir).map_err(|source| GameboyError::FileWrite {
+            path: output_dir.to_string_lossy().into_owned(),
+            source,
+        })?;
+
+        Ok(Printer {
+            output_dir,
+            next_print_id: 1,
+            printed_files: Vec::new(),
+
+            state: PacketState::Magic1,
+            command: 0,
+            compressed: false,
+            length: 0,
+            packet_data: Vec::new(),
+            checksum: 0,
+            computed_checksum: 0,
+
+            image_data: Vec::new(),
+            status: 0,
+            busy_packets: 0,
+        })
+    }
+
+    pub fn get_printed_files(&self) -> &[PathBuf] {
+        &self.printed_files
+    }
+
+    //takes one byte from the game, returns the byte shifted back at the same time
+    fn receive(&mut self, byte: u8) -> u8 {
+        match self.state {
+            PacketState::Magic1 => {
+                if byte == MAGIC_1 {
+                    self.state = PacketState::Magic2;
+                }
+            }
+            PacketState::Magic2 => {
+                self.state = if byte == MAGIC_2 { PacketState::Command } else { PacketState::Magic1 };
+            }
+            PacketState::Command => {
+                self.command = byte;
+                self.computed_checksum = byte as u16;
+                self.state = PacketState::Compression;
+            }
+            PacketState::Compression => {
+                self.compressed = byte & 0x1 != 0;
+                self.computed_checksum = self.computed_checksum.wrapping_add(byte as u16);
+                self.state = PacketState::LengthLow;
+            }
+            PacketState::LengthLow => {
+                self.length = byte as u16;
+                self.computed_checksum = self.computed_checksum.wrapping_add(byte as u16);
+                self.state = PacketState::LengthHigh;
+            }
+            PacketState::LengthHigh => {
+                self.length |= (byte as u16) << 8;
+                self.computed_checksum = self.computed_checksum.wrapping_add(byte as u16);
+                self.packet_data.clear();
+                self.state = if self.length == 0 { PacketState::ChecksumLow } else { PacketState::Data };
+            }
+            PacketState::Data => {
+                self.packet_data.push(byte);
+                self.computed_checksum = self.computed_checksum.wrapping_add(byte as u16);
+                if self.packet_data.len() == self.length as usize {
+                    self.state = PacketState::ChecksumLow;
+                }
+            }
+            PacketState::ChecksumLow => {
+                self.checksum = byte as u16;
+                self.state = PacketState::ChecksumHigh;
+            }
+            PacketState::ChecksumHigh => {
+                self.checksum |= (byte as u16) << 8;
+                self.state = PacketState::Ack;
+            }
+            PacketState::Ack => {
+                self.state = PacketState::Status;
+                return PRINTER_ID;
+            }
+            PacketState::Status => {
+                self.state = PacketState::Magic1;
+                let status = self.status;
+                self.finish_packet();
+                return status;
+            }
+        }
+        0x00
+    }
+
+    //runs after the status byte so the reply reflects the state before this packet
+    fn finish_packet(&mut self) {
+        if self.busy_packets > 0 {
+            self.busy_packets -= 1;
+            if self.busy_packets == 0 {
+                self.status &= !(STATUS_PRINTING | STATUS_IMAGE_FULL);
+            }
+        }
+
+        if self.checksum != self.computed_checksum {
+            warn!("Printer packet checksum mismatch, {:#06x} != {:#06x}", self.checksum, self.computed_checksum);
+            self.status |= STATUS_CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !STATUS_CHECKSUM_ERROR;
+
+        match self.command {
+            CMD_INIT => {
+                self.image_data.clear();
+                self.status = 0;
+                self.busy_packets = 0;
+            }
+            CMD_DATA => {
+                let data = if self.compressed { decompress(&self.packet_data) } else { self.packet_data.clone() };
+                let space = MAX_IMAGE_BYTES - self.image_data.len();
+                self.image_data.extend_from_slice(&data[..data.len().min(space)]);
+                if !self.image_data.is_empty() {
+                    self.status |= STATUS_UNPROCESSED;
+                }
+            }
+            CMD_PRINT => {
+                if self.packet_data.len() < 4 {
+                    self.status |= STATUS_PACKET_ERROR;
+                    return;
+                }
+                let sheets = self.packet_data[0];
+                let margins = self.packet_data[1];
+                let palette = match self.packet_data[2] {
+                    0x00 => DEFAULT_PALETTE,
+                    palette => palette,
+                };
+
+                if sheets > 0 && !self.image_data.is_empty() {
+                    self.print(margins, palette);
+                }
+                self.image_data.clear();
+                self.status = (self.status & !STATUS_UNPROCESSED) | STATUS_PRINTING | STATUS_IMAGE_FULL;
+                self.busy_packets = PRINT_BUSY_PACKETS;
+            }
+            CMD_STATUS => {}
+            _ => {
+                warn!("Unknown printer command {:#04x}", self.command);
+                self.status |= STATUS_PACKET_ERROR;
+            }
+        }
+    }
+
+    fn print(&mut self, margins: u8, palette: u8) {
+        let top_margin = (margins >> 4) as usize * MARGIN_UNIT_LINES;
+        let bottom_margin = (margins & 0xf) as usize * MARGIN_UNIT_LINES;
+        let image_lines = self.image_data.len() / (TILE_BYTES * TILES_PER_ROW) * 8;
+        let height = top_margin + image_lines + bottom_margin;
+
+        let mut pixels = vec![SHADES[0]; PRINT_WIDTH * height];
+        for line in 0..image_lines {
+            let tile_row = line / 8;
+            let line_in_tile = line % 8;
+            for tile_x in 0..TILES_PER_ROW {
+                let tile_addr = (tile_row * TILES_PER_ROW + tile_x) * TILE_BYTES + line_in_tile * 2;
+                let low = self.image_data[tile_addr];
+                let high = self.image_data[tile_addr + 1];
+                for bit in 0..8 {
+                    let color = (((high >> (7 - bit)) & 0x1) << 1) | ((low >> (7 - bit)) & 0x1);
+                    let shade = (palette >> (color * 2)) & 0x3;
+                    pixels[(top_margin + line) * PRINT_WIDTH + tile_x * 8 + bit] = SHADES[shade as usize];
+                }
+            }
+        }
+
+        let path = self.next_output_path();
+        match write_png(&path, &pixels, PRINT_WIDTH as u32, height as u32) {
+            Ok(()) => {
+                info!("Printed {} lines to {}", image_lines, path.display());
+                self.printed_files.push(path);
+            }
+            Err(e) => error!("Failed to write print to {}: {}", path.display(), e),
+        }
+    }
+
+    fn next_output_path(&mut self) -> PathBuf {
+        loop {
+            let path = self.output_dir.join(format!("print_{:04}.png", self.next_print_id));
+            self.next_print_id += 1;
+            if !path.exists() {
+                return path;
+            }
+        }
+    }
+}
+
+//the game always drives the clock when printing, so every byte arrives as a master
+//transfer and the printer's reply is what gets shifted back
+impl LinkEndpoint for Printer {
+    fn exchange_as_master(&mut self, out: u8) -> u8 {
+        self.receive(out)
+    }
+
+    fn poll_as_slave(&mut self, _out: u8) -> Option<u8> {
+        None
+    }
+}
+
+//control byte with bit 7 set repeats the next byte (n & 0x7f) + 2 times, otherwise the
+//next n + 1 bytes are copied as is
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let control = data[pos];
+        pos += 1;
+        if control & 0x80 != 0 {
+            let count = (control & 0x7f) as usize + 2;
+            if let Some(byte) = data.get(pos) {
+                out.extend(std::iter::repeat_n(*byte, count));
+            }
+            pos += 1;
+        } else {
+            let count = control as usize + 1;
+            let end = (pos + count).min(data.len());
+            out.extend_from_slice(&data[pos..end]);
+            pos = end;
+        }
+    }
+    out
+}
+
+fn write_png(path: &Path, pixels: &[u8], width: u32, height: u32) -> Result<(), png::EncodingError> {
+    let file = File::create(path)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels)?;
+    Ok(())
+}
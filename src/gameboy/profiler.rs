@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use super::error::GameboyError;
+use super::symbols::SymbolTable;
+use super::Gameboy;
+
+//calls that never return (stack resets, jumps through pushed addresses) would grow the stack
+//forever, past this depth the outermost frames are dropped
+const MAX_CALL_DEPTH: usize = 256;
+
+//rom bank and address, the bank is 0 outside switchable rom
+pub type CodeLocation = (usize, u16);
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InstructionStats {
+    pub cycles: u64,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FunctionStats {
+    //cycles of instructions inside the function itself
+    pub self_cycles: u64,
+    //cycles from the end of the call to the end of the return, callees included
+    pub inclusive_cycles: u64,
+    pub calls: u64,
+}
+
+struct Frame {
+    function: CodeLocation,
+    //where the return address was pushed
+    sp: u16,
+    //profiler clock when the call finished, set once the calling instruction is done
+    start: Option<u64>,
+}
+
+//counts cycles per instruction address and per called function. functions are keyed by the
+//call, rst or interrupt target, code run before any call goes to the None entry
+pub struct Profiler {
+    clock: u64,
+    halted_cycles: u64,
+    instructions: HashMap<CodeLocation, InstructionStats>,
+    functions: HashMap<Option<CodeLocation>, FunctionStats>,
+    stack: Vec<Frame>,
+    returned: Vec<Frame>,
+    current: Option<(CodeLocation, Option<CodeLocation>)>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            clock: 0,
+            halted_cycles: 0,
+            instructions: HashMap::new(),
+            functions: HashMap::new(),
+            stack: Vec::new(),
+            returned: Vec::new(),
+            current: None,
+        }
+    }
+
+    pub fn get_total_cycles(&self) -> u64 {
+        self.clock
+    }
+
+    pub fn get_halted_cycles(&self) -> u64 {
+        self.halted_cycles
+    }
+
+    pub fn get_instructions(&self) -> &HashMap<CodeLocation, InstructionStats> {
+        &self.instructions
+    }
+
+    pub fn get_functions(&self) -> &HashMap<Option<CodeLocation>, FunctionStats> {
+        &self.functions
+    }
+
+    fn current_function(&self) -> Option<CodeLocation> {
+        self.stack.last().map(|frame| frame.function)
+    }
+
+    fn enter(&mut self, function: CodeLocation, sp: u16, start: Option<u64>) {
+        if self.stack.len() >= MAX_CALL_DEPTH {
+            self.stack.remove(0);
+        }
+        self.stack.push(Frame { function, sp, start });
+        self.functions.entry(Some(function)).or_default().calls += 1;
+    }
+
+    //a return pops every frame whose return address is now below the stack pointer
+    fn leave(&mut self, sp: u16) {
+        while self.stack.last().is_some_and(|frame| frame.sp < sp) {
+            let frame = self.stack.pop().unwrap();
+            self.returned.push(frame);
+        }
+    }
+
+    fn finish_instruction(&mut self, cycles: u64) {
+        self.clock += cycles;
+        if let Some((location, owner)) = self.current.take() {
+            let stats = self.instructions.entry(location).or_default();
+            stats.cycles += cycles;
+            stats.count += 1;
+            self.functions.entry(owner).or_default().self_cycles += cycles;
+        }
+
+        for frame in self.stack.iter_mut().rev() {
+            if frame.start.is_some() {
+                break;
+            }
+            frame.start = Some(self.clock);
+        }
+
+        for frame in std::mem::take(&mut self.returned) {
+            //a recursive call is already covered by the outer one
+            let recursive = self.stack.iter().any(|outer| outer.function == frame.function);
+            if let (Some(start), false) = (frame.start, recursive) {
+                self.functions.entry(Some(frame.function)).or_default().inclusive_cycles += self.clock - start;
+            }
+        }
+    }
+
+    //text report with the `limit` busiest functions by self and inclusive cycles and the
+    //busiest instructions, labelled from `symbols` when a .sym file is loaded
+    pub fn report(&self, limit: usize, symbols: Option<&SymbolTable>) -> String {
+        let name = |location: Option<CodeLocation>| match location {
+            Some((bank, addr)) => match symbols.and_then(|s| s.symbolize(bank, addr)) {
+                Some(label) => format!("{:02x}:{:04x}  {}", bank, addr, label),
+                None => format!("{:02x}:{:04x}", bank, addr),
+            },
+            None => String::from("(outside any call)"),
+        };
+        let percent = |cycles: u64| cycles as f64 * 100.0 / self.clock.max(1) as f64;
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "profiled {} cycles, {} ({:.1}%) spent halted",
+            self.clock,
+            self.halted_cycles,
+            percent(self.halted_cycles)
+        );
+
+        let mut functions: Vec<(&Option<CodeLocation>, &FunctionStats)> = self.functions.iter().collect();
+        for inclusive in [false, true] {
+            let key = |stats: &FunctionStats| if inclusive { stats.inclusive_cycles } else { stats.self_cycles };
+            functions.sort_by(|a, b| key(b.1).cmp(&key(a.1)).then(a.0.cmp(b.0)));
+            let _ = writeln!(out, "\nfunctions by {} cycles", if inclusive { "inclusive" } else { "self" });
+            let _ = writeln!(out, "{:>12} {:>6} {:>12} {:>6} {:>8}  function", "self", "%", "inclusive", "%", "calls");
+            for (location, stats) in functions.iter().take(limit) {
+                let _ = writeln!(
+                    out,
+                    "{:>12} {:>5.1}% {:>12} {:>5.1}% {:>8}  {}",
+                    stats.self_cycles,
+                    percent(stats.self_cycles),
+                    stats.inclusive_cycles,
+                    percent(stats.inclusive_cycles),
+                    stats.calls,
+                    name(**location)
+                );
+            }
+        }
+
+        let mut instructions: Vec<(&CodeLocation, &InstructionStats)> = self.instructions.iter().collect();
+        instructions.sort_by(|a, b| b.1.cycles.cmp(&a.1.cycles).then(a.0.cmp(b.0)));
+        let _ = writeln!(out, "\ninstructions by cycles");
+        let _ = writeln!(out, "{:>12} {:>6} {:>10}  address", "cycles", "%", "count");
+        for (location, stats) in instructions.iter().take(limit) {
+            let _ = writeln!(
+                out,
+                "{:>12} {:>5.1}% {:>10}  {}",
+                stats.cycles,
+                percent(stats.cycles),
+                stats.count,
+                name(Some(**location))
+            );
+        }
+        out
+    }
+}
+
+//called before an instruction runs, remembers where it is and which function it belongs to
+pub(super) fn start_instruction(gb: &mut Gameboy) {
+    if gb.profiler.is_none() {
+        return;
+    }
+    let location = (gb.rom.rom_bank(gb.pc.0), gb.pc.0);
+    let profiler = gb.profiler.as_mut().unwrap();
+    profiler.current = Some((location, profiler.current_function()));
+}
+
+pub(super) fn finish_instruction(gb: &mut Gameboy, cycles: u32) {
+    if let Some(profiler) = &mut gb.profiler {
+        profiler.finish_instruction(cycles as u64);
+    }
+}
+
+pub(super) fn on_halted(gb: &mut Gameboy, cycles: u32) {
+    if let Some(profiler) = &mut gb.profiler {
+        profiler.clock += cycles as u64;
+        profiler.halted_cycles += cycles as u64;
+    }
+}
+
+//a call or rst just jumped to pc
+pub(super) fn on_call(gb: &mut Gameboy) {
+    if gb.profiler.is_none() {
+        return;
+    }
+    let function = (gb.rom.rom_bank(gb.pc.0), gb.pc.0);
+    let sp = gb.sp.0;
+    gb.profiler.as_mut().unwrap().enter(function, sp, None);
+}
+
+//a ret or reti just popped the return address
+pub(super) fn on_return(gb: &mut Gameboy) {
+    let sp = gb.sp.0;
+    if let Some(profiler) = &mut gb.profiler {
+        profiler.leave(sp);
+    }
+}
+
+//an interrupt dispatch counts as a call to its vector, the dispatch cycles go to the handler
+pub(super) fn on_interrupt(gb: &mut Gameboy, cycles: u32) {
+    if gb.profiler.is_none() {
+        return;
+    }
+    let function = (gb.rom.rom_bank(gb.pc.0), gb.pc.0);
+    let sp = gb.sp.0;
+    let profiler = gb.profiler.as_mut().unwrap();
+    profiler.enter(function, sp, Some(profiler.clock));
+    profiler.clock += cycles as u64;
+    profiler.functions.entry(Some(function)).or_default().self_cycles += cycles as u64;
+}
+
+impl Gameboy {
+    //starts counting from zero, replacing any profile collected so far
+    pub fn enable_profiler(&mut self) {
+        self.profiler = Some(Profiler::new());
+    }
+
+    pub fn disable_profiler(&mut self) {
+        self.profiler = None;
+    }
+
+    pub fn get_profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    pub fn profile_report(&self, limit: usize) -> Option<String> {
+        Some(self.profiler.as_ref()?.report(limit, self.symbols.as_ref()))
+    }
+
+    pub fn write_profile_report(&self, path: &Path, limit: usize) -> Result<(), GameboyError> {
+        let report = self.profile_report(limit).unwrap_or_default();
+        fs::write(path, report).map_err(|source| GameboyError::FileWrite {
+            path: path.to_string_lossy().into_owned(),
+            source,
+        })
+    }
+}
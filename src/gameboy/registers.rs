@@ -1,5 +1,8 @@
 use core::num::Wrapping as W;
 
+use super::error::GameboyError;
+use super::save_state::{StateReader, StateWriter};
+
 pub struct Registers {
     pub a: W<u8>,
     f: W<u8>,
@@ -17,9 +20,15 @@ const FLAG_H: W<u8> = W(0x20);
 const FLAG_C: W<u8> = W(0x10);
 const FLAGS_ALL: W<u8> = W(FLAG_C.0 | FLAG_H.0 | FLAG_N.0 | FLAG_Z.0);
 
+impl Default for Registers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Registers {
     pub fn new() -> Registers {
-        return Registers {
+        Registers {
             a: W(0u8),
             f: W(0u8),
             b: W(0u8),
@@ -28,7 +37,7 @@ impl Registers {
             e: W(0u8),
             h: W(0u8),
             l: W(0u8),
-        };
+        }
     }
 
     #[inline(always)]
@@ -161,4 +170,19 @@ impl Registers {
     pub fn unset_flag_c(&mut self) {
         self.f &= !FLAG_C;
     }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_u16(self.get_af().0);
+        w.write_u16(self.get_bc().0);
+        w.write_u16(self.get_de().0);
+        w.write_u16(self.get_hl().0);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), GameboyError> {
+        self.set_af(W(r.read_u16()?));
+        self.set_bc(W(r.read_u16()?));
+        self.set_de(W(r.read_u16()?));
+        self.set_hl(W(r.read_u16()?));
+        Ok(())
+    }
 }
@@ -1,77 +1,98 @@
 use std::time::{Duration, Instant};
 
-use log::debug;
-use minifb::{Key, Window, WindowOptions};
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
+
+use super::input::InputKey;
+use super::ppu::{GB_SCREEN_HEIGHT, GB_SCREEN_WIDTH};
 
 extern crate spin_sleep;
 
-const GB_SCREEN_WIDTH: usize = 160;
-const GB_SCREEN_HEIGHT: usize = 144;
 const SIZE_MULTIPLIER: usize = 3;
 const WINDOW_WIDTH: usize = GB_SCREEN_WIDTH * SIZE_MULTIPLIER;
 const WINDOW_HEIGHT: usize = GB_SCREEN_HEIGHT * SIZE_MULTIPLIER;
 const FRAME_TIME_MICROS: u64 = 16450;
+const WINDOW_TITLE: &str = "LegumeGB_rs";
+//F1-F4 load a slot, shift+F1-F4 saves to it
+const STATE_SLOT_KEYS: [Key; 4] = [Key::F1, Key::F2, Key::F3, Key::F4];
+const REWIND_KEY: Key = Key::Backspace;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StateSlotAction {
+    Save(usize),
+    Load(usize),
+}
 
-pub struct InputKey {
-    state_just_changed: bool,
-    hardware_key: Key,
-    currently_held: bool,
+pub struct Renderer {
+    window: Window,
+    screens: usize,
+    last_frame_time: Instant,
+    pub keys: Vec<InputKey>,
+    key_map: Vec<Key>,
+    pub second_keys: Vec<InputKey>,
+    second_key_map: Vec<Key>,
+    rumble_active: bool,
+    status: Option<String>,
+    state_slot_action: Option<StateSlotAction>,
+    rewind_held: bool,
 }
 
-impl InputKey {
-    pub fn new(hardware_key: Key) -> Self {
-        Self {
-            state_just_changed: false,
-            hardware_key: hardware_key,
-            currently_held: false,
-        }
+impl Default for Renderer {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    pub fn get_held(&self) -> bool {
-        self.currently_held
-    }
+impl Renderer {
+    pub fn new() -> Renderer {
+        let mut ret = Renderer::with_screens(1);
+
+        ret.map_key(Key::Enter); //start
+        ret.map_key(Key::Space); //sel
+        ret.map_key(Key::S); //b
+        ret.map_key(Key::A); //a
+        ret.map_key(Key::Down); //down
+        ret.map_key(Key::Up); //up
+        ret.map_key(Key::Left); //left
+        ret.map_key(Key::Right); //right
+        ret.map_key(Key::Q); //debug swap tilemap bg
+        ret.map_key(Key::W); //debug swap tiledata bg
+        ret.map_key(Key::E); //debug swap tilemap win
+        ret.map_key(Key::R); //debug swap tiledata win
+        ret.map_key(Key::T); //debug toggle window
 
-    pub fn get_state_just_changed(&self) -> bool {
-        self.state_just_changed
+        ret
     }
 
-    fn update(&mut self, window: &Window) {
-        if window.is_key_down(self.hardware_key) {
-            if self.currently_held {
-                self.state_just_changed = false;
-            } else {
-                self.currently_held = true;
-                self.state_just_changed = true;
-            }
-        }
-
-        if !window.is_key_down(self.hardware_key) {
-            if !self.currently_held {
-                self.state_just_changed = false;
-            } else {
-                self.currently_held = false;
-                self.state_just_changed = true;
-            }
-        }
-    }
+    //two screens side by side for linked play, the first player is on the left half of
+    //the keyboard and the second on the right, no debug keys
+    pub fn new_dual() -> Renderer {
+        let mut ret = Renderer::with_screens(2);
+
+        ret.map_key(Key::Key1); //start
+        ret.map_key(Key::Key2); //sel
+        ret.map_key(Key::F); //b
+        ret.map_key(Key::G); //a
+        ret.map_key(Key::S); //down
+        ret.map_key(Key::W); //up
+        ret.map_key(Key::A); //left
+        ret.map_key(Key::D); //right
+
+        ret.map_second_key(Key::Enter); //start
+        ret.map_second_key(Key::P); //sel
+        ret.map_second_key(Key::K); //b
+        ret.map_second_key(Key::L); //a
+        ret.map_second_key(Key::Down); //down
+        ret.map_second_key(Key::Up); //up
+        ret.map_second_key(Key::Left); //left
+        ret.map_second_key(Key::Right); //right
 
-    pub fn copy_state_from_other(&mut self, other: &InputKey) {
-        self.currently_held = other.currently_held;
-        self.state_just_changed = other.state_just_changed;
+        ret
     }
-}
-
-pub struct Renderer {
-    window: Window,
-    last_frame_time: Instant,
-    pub keys: Vec<InputKey>,
-}
 
-impl Renderer {
-    pub fn new() -> Renderer {
+    fn with_screens(screens: usize) -> Renderer {
         let mut window = Window::new(
-            "LegumeGB_rs",
-            WINDOW_WIDTH,
+            WINDOW_TITLE,
+            WINDOW_WIDTH * screens,
             WINDOW_HEIGHT,
             WindowOptions::default(),
         )
@@ -80,46 +101,113 @@ impl Renderer {
         });
         window.set_target_fps(0);
 
-        let mut ret = Renderer {
+        Renderer {
             window,
+            screens,
             last_frame_time: Instant::now(),
-            keys: Vec::<InputKey>::with_capacity(8),
-        };
-
-        ret.keys.push(InputKey::new(Key::Enter)); //start
-        ret.keys.push(InputKey::new(Key::Space)); //sel
-        ret.keys.push(InputKey::new(Key::S)); //b
-        ret.keys.push(InputKey::new(Key::A)); //a
-        ret.keys.push(InputKey::new(Key::Down)); //down
-        ret.keys.push(InputKey::new(Key::Up)); //up
-        ret.keys.push(InputKey::new(Key::Left)); //left
-        ret.keys.push(InputKey::new(Key::Right)); //right
-        ret.keys.push(InputKey::new(Key::Q)); //debug swap tilemap bg
-        ret.keys.push(InputKey::new(Key::W)); //debug swap tiledata bg
-        ret.keys.push(InputKey::new(Key::E)); //debug swap tilemap win
-        ret.keys.push(InputKey::new(Key::R)); //debug swap tiledata win
-        ret.keys.push(InputKey::new(Key::T)); //debug toggle window
+            keys: Vec::<InputKey>::with_capacity(13),
+            key_map: Vec::<Key>::with_capacity(13),
+            second_keys: Vec::<InputKey>::new(),
+            second_key_map: Vec::<Key>::new(),
+            rumble_active: false,
+            status: None,
+            state_slot_action: None,
+            rewind_held: false,
+        }
+    }
 
-        ret
+    fn map_key(&mut self, hardware_key: Key) {
+        self.keys.push(InputKey::new());
+        self.key_map.push(hardware_key);
+    }
+
+    fn map_second_key(&mut self, hardware_key: Key) {
+        self.second_keys.push(InputKey::new());
+        self.second_key_map.push(hardware_key);
+    }
+
+    //minifb has no force feedback, so rumble is surfaced in the window title
+    pub fn set_rumble(&mut self, active: bool) {
+        if active == self.rumble_active {
+            return;
+        }
+
+        self.rumble_active = active;
+        self.update_title();
+    }
+
+    //shown after the title, for things like a locked up cpu
+    pub fn set_status(&mut self, status: Option<String>) {
+        if status == self.status {
+            return;
+        }
+
+        self.status = status;
+        self.update_title();
+    }
+
+    fn update_title(&mut self) {
+        let mut title = String::from(WINDOW_TITLE);
+        if self.rumble_active {
+            title.push_str(" [rumble]");
+        }
+        if let Some(status) = &self.status {
+            title.push_str(" - ");
+            title.push_str(status);
+        }
+        self.window.set_title(&title);
+    }
+
+    //slots are numbered from 1, the action is cleared once taken
+    pub fn take_state_slot_action(&mut self) -> Option<StateSlotAction> {
+        self.state_slot_action.take()
+    }
+
+    pub fn is_rewind_held(&self) -> bool {
+        self.rewind_held
     }
 
     pub fn process_frame(&mut self, display: &[u32]) -> bool {
-        let mut buffer: Vec<u32> = vec![0; WINDOW_WIDTH * WINDOW_HEIGHT];
+        self.process_frames(&[display])
+    }
 
-        for y in 0..WINDOW_HEIGHT {
-            for x in 0..WINDOW_WIDTH {
-                let value =
-                    display[(x / SIZE_MULTIPLIER) + (y / SIZE_MULTIPLIER) * GB_SCREEN_WIDTH];
-                buffer[x + y * WINDOW_WIDTH] = value;
+    //draws one screen per display, left to right
+    pub fn process_frames(&mut self, displays: &[&[u32]]) -> bool {
+        let buffer_width = WINDOW_WIDTH * self.screens;
+        let mut buffer: Vec<u32> = vec![0; buffer_width * WINDOW_HEIGHT];
+
+        for (screen, display) in displays.iter().take(self.screens).enumerate() {
+            for y in 0..WINDOW_HEIGHT {
+                for x in 0..WINDOW_WIDTH {
+                    let value =
+                        display[(x / SIZE_MULTIPLIER) + (y / SIZE_MULTIPLIER) * GB_SCREEN_WIDTH];
+                    buffer[screen * WINDOW_WIDTH + x + y * buffer_width] = value;
+                }
             }
         }
 
         self.window
-            .update_with_buffer(&buffer, WINDOW_WIDTH, WINDOW_HEIGHT)
+            .update_with_buffer(&buffer, buffer_width, WINDOW_HEIGHT)
             .unwrap();
 
-        for i in &mut self.keys {
-            i.update(&self.window);
+        for (key, hardware_key) in self.keys.iter_mut().zip(self.key_map.iter()) {
+            key.set_held(self.window.is_key_down(*hardware_key));
+        }
+        for (key, hardware_key) in self.second_keys.iter_mut().zip(self.second_key_map.iter()) {
+            key.set_held(self.window.is_key_down(*hardware_key));
+        }
+
+        self.rewind_held = self.window.is_key_down(REWIND_KEY);
+
+        let shift_held = self.window.is_key_down(Key::LeftShift) || self.window.is_key_down(Key::RightShift);
+        for (idx, slot_key) in STATE_SLOT_KEYS.iter().enumerate() {
+            if self.window.is_key_pressed(*slot_key, KeyRepeat::No) {
+                self.state_slot_action = Some(if shift_held {
+                    StateSlotAction::Save(idx + 1)
+                } else {
+                    StateSlotAction::Load(idx + 1)
+                });
+            }
         }
 
         if !self.window.is_key_down(Key::LeftCtrl) {
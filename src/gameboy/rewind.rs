@@ -0,0 +1,155 @@
+use std::collections::VecDeque;
+
+use log::warn;
+
+pub const DEFAULT_REWIND_SECONDS: usize = 60;
+pub const DEFAULT_REWIND_FRAME_INTERVAL: u32 = 2;
+const FRAMES_PER_SECOND: usize = 60;
+
+//the newest snapshot is kept whole, every older one is stored as the xor against the
+//snapshot after it, with runs of unchanged bytes squashed. stepping back undoes one delta
+pub struct RewindBuffer {
+    head: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+    max_snapshots: usize,
+    frame_interval: u32,
+    frames_since_snapshot: u32,
+}
+
+impl RewindBuffer {
+    pub fn new(max_snapshots: usize, frame_interval: u32) -> RewindBuffer {
+        RewindBuffer {
+            head: None,
+            deltas: VecDeque::new(),
+            max_snapshots: max_snapshots.max(1),
+            frame_interval: frame_interval.max(1),
+            frames_since_snapshot: 0,
+        }
+    }
+
+    pub fn with_seconds(seconds: usize, frame_interval: u32) -> RewindBuffer {
+        let frame_interval = frame_interval.max(1);
+        RewindBuffer::new(seconds * FRAMES_PER_SECOND / frame_interval as usize, frame_interval)
+    }
+
+    //called once per frame, returns true when a snapshot should be pushed
+    pub fn frame_tick(&mut self) -> bool {
+        self.frames_since_snapshot += 1;
+        if self.frames_since_snapshot >= self.frame_interval {
+            self.frames_since_snapshot = 0;
+            return true;
+        }
+        false
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(head) = self.head.take() {
+            if head.len() == state.len() {
+                self.deltas.push_back(encode_delta(&head, &state));
+            } else {
+                //deltas only work between snapshots of the same size, so older history is lost
+                warn!(
+                    "Save state size changed from {} to {} bytes, dropping {} rewind snapshots",
+                    head.len(),
+                    state.len(),
+                    self.deltas.len() + 1
+                );
+                self.deltas.clear();
+            }
+        }
+        self.head = Some(state);
+
+        //the head counts as one of the snapshots
+        while self.deltas.len() + 1 > self.max_snapshots {
+            self.deltas.pop_front();
+        }
+    }
+
+    //drops the newest snapshot and returns the one before it, none once history runs out
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let delta = self.deltas.pop_back()?;
+        let head = self.head.as_mut()?;
+        apply_delta(head, &delta);
+        self.frames_since_snapshot = 0;
+        Some(head.clone())
+    }
+
+    pub fn clear(&mut self) {
+        self.head = None;
+        self.deltas.clear();
+        self.frames_since_snapshot = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.head.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    pub fn memory_used(&self) -> usize {
+        self.head.as_ref().map_or(0, |h| h.len()) + self.deltas.iter().map(|d| d.len()).sum::<usize>()
+    }
+}
+
+//delta stream is a list of (unchanged run, changed run, changed bytes), lengths as varints
+fn encode_delta(older: &[u8], newer: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+
+    while pos < older.len() {
+        let zero_start = pos;
+        while pos < older.len() && older[pos] == newer[pos] {
+            pos += 1;
+        }
+        let literal_start = pos;
+        while pos < older.len() && older[pos] != newer[pos] {
+            pos += 1;
+        }
+
+        write_varint(&mut out, literal_start - zero_start);
+        write_varint(&mut out, pos - literal_start);
+        for idx in literal_start..pos {
+            out.push(older[idx] ^ newer[idx]);
+        }
+    }
+    out
+}
+
+fn apply_delta(state: &mut [u8], delta: &[u8]) {
+    let mut pos = 0;
+    let mut delta_pos = 0;
+
+    while delta_pos < delta.len() {
+        pos += read_varint(delta, &mut delta_pos);
+        let literal_len = read_varint(delta, &mut delta_pos);
+        for byte in &delta[delta_pos..delta_pos + literal_len] {
+            state[pos] ^= byte;
+            pos += 1;
+        }
+        delta_pos += literal_len;
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0usize;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
@@ -0,0 +1,381 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::error::GameboyError;
+use super::save_state::{StateReader, StateWriter};
+
+pub const RTC_SAVE_SIZE: usize = 48;
+const RTC_SAVE_SIZE_SHORT_TIMESTAMP: usize = 44;
+
+const RTC_SECONDS: u8 = 0x08;
+const RTC_MINUTES: u8 = 0x09;
+const RTC_HOURS: u8 = 0x0a;
+const RTC_DAYS_LOW: u8 = 0x0b;
+const RTC_DAYS_HIGH: u8 = 0x0c;
+
+const DH_DAY_BIT_8: u8 = 0x01;
+const DH_HALT: u8 = 0x40;
+const DH_DAY_CARRY: u8 = 0x80;
+
+pub trait RtcClock {
+    //seconds since the unix epoch
+    fn now(&self) -> u64;
+}
+
+pub struct SystemRtcClock;
+
+impl RtcClock for SystemRtcClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct RtcRegisters {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days: u16,
+    halt: bool,
+    day_carry: bool,
+}
+
+impl RtcRegisters {
+    fn read(&self, select: u8) -> u8 {
+        match select {
+            RTC_SECONDS => self.seconds,
+            RTC_MINUTES => self.minutes,
+            RTC_HOURS => self.hours,
+            RTC_DAYS_LOW => self.days as u8,
+            RTC_DAYS_HIGH => {
+                let mut val = ((self.days >> 8) as u8) & DH_DAY_BIT_8;
+                if self.halt {
+                    val |= DH_HALT;
+                }
+                if self.day_carry {
+                    val |= DH_DAY_CARRY;
+                }
+                val
+            }
+            _ => super::UNDEFINED_READ,
+        }
+    }
+
+    fn advance(&mut self, elapsed_secs: u64) {
+        let total_secs = self.seconds as u64 + elapsed_secs;
+        self.seconds = (total_secs % 60) as u8;
+
+        let total_mins = self.minutes as u64 + total_secs / 60;
+        self.minutes = (total_mins % 60) as u8;
+
+        let total_hours = self.hours as u64 + total_mins / 60;
+        self.hours = (total_hours % 24) as u8;
+
+        let total_days = self.days as u64 + total_hours / 24;
+        if total_days > 0x1ff {
+            self.day_carry = true;
+        }
+        self.days = (total_days & 0x1ff) as u16;
+    }
+
+    //the save trailer stores each register as a 32 bit little endian value
+    fn write_save(&self, out: &mut Vec<u8>) {
+        let regs = [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.read(RTC_DAYS_LOW),
+            self.read(RTC_DAYS_HIGH),
+        ];
+        for reg in regs {
+            out.extend_from_slice(&(reg as u32).to_le_bytes());
+        }
+    }
+
+    fn read_save(data: &[u8]) -> RtcRegisters {
+        let reg = |idx: usize| data[idx * 4];
+        let days_high = reg(4);
+
+        RtcRegisters {
+            seconds: reg(0) & 0x3f,
+            minutes: reg(1) & 0x3f,
+            hours: reg(2) & 0x1f,
+            days: reg(3) as u16 | (((days_high & DH_DAY_BIT_8) as u16) << 8),
+            halt: days_high & DH_HALT != 0,
+            day_carry: days_high & DH_DAY_CARRY != 0,
+        }
+    }
+}
+
+pub struct Rtc {
+    live: RtcRegisters,
+    latched: RtcRegisters,
+    last_update: u64,
+    latch_armed: bool,
+    clock: Box<dyn RtcClock>,
+}
+
+impl Default for Rtc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Rtc {
+    pub fn new() -> Rtc {
+        let clock: Box<dyn RtcClock> = Box::new(SystemRtcClock);
+        Rtc {
+            live: RtcRegisters::default(),
+            latched: RtcRegisters::default(),
+            last_update: clock.now(),
+            latch_armed: false,
+            clock,
+        }
+    }
+
+    pub fn set_clock(&mut self, clock: Box<dyn RtcClock>) {
+        self.update();
+        self.last_update = clock.now();
+        self.clock = clock;
+    }
+
+    pub fn is_register(select: u8) -> bool {
+        (RTC_SECONDS..=RTC_DAYS_HIGH).contains(&select)
+    }
+
+    fn update(&mut self) {
+        let now = self.clock.now();
+        if !self.live.halt && now > self.last_update {
+            self.live.advance(now - self.last_update);
+        }
+        self.last_update = now;
+    }
+
+    //latching happens on a 0x00 then 0x01 write to 0x6000-0x7fff
+    pub fn write_latch(&mut self, value: u8) {
+        if self.latch_armed && value == 0x01 {
+            self.update();
+            self.latched = self.live;
+        }
+        self.latch_armed = value == 0x00;
+    }
+
+    pub fn read_register(&self, select: u8) -> u8 {
+        self.latched.read(select)
+    }
+
+    pub fn write_register(&mut self, select: u8, value: u8) {
+        self.update();
+
+        match select {
+            RTC_SECONDS => {
+                self.live.seconds = value & 0x3f;
+            }
+            RTC_MINUTES => {
+                self.live.minutes = value & 0x3f;
+            }
+            RTC_HOURS => {
+                self.live.hours = value & 0x1f;
+            }
+            RTC_DAYS_LOW => {
+                self.live.days = (self.live.days & 0x100) | value as u16;
+            }
+            RTC_DAYS_HIGH => {
+                self.live.days = (self.live.days & 0xff) | (((value & DH_DAY_BIT_8) as u16) << 8);
+                self.live.halt = value & DH_HALT != 0;
+                self.live.day_carry = value & DH_DAY_CARRY != 0;
+            }
+            _ => {}
+        }
+    }
+
+    //48 byte trailer: live registers, latched registers, then a 64 bit unix timestamp
+    //of when the live registers were last brought up to date
+    pub fn save(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(RTC_SAVE_SIZE);
+        self.live.write_save(&mut out);
+        self.latched.write_save(&mut out);
+        out.extend_from_slice(&self.last_update.to_le_bytes());
+        out
+    }
+
+    //accepts both the 48 byte trailer and the older 44 byte one with a 32 bit timestamp
+    pub fn load(&mut self, data: &[u8]) -> bool {
+        let timestamp = match data.len() {
+            RTC_SAVE_SIZE => u64::from_le_bytes(data[40..48].try_into().unwrap()),
+            RTC_SAVE_SIZE_SHORT_TIMESTAMP => u32::from_le_bytes(data[40..44].try_into().unwrap()) as u64,
+            _ => return false,
+        };
+
+        self.live = RtcRegisters::read_save(&data[0..20]);
+        self.latched = RtcRegisters::read_save(&data[20..40]);
+        self.last_update = timestamp;
+        self.update();
+        true
+    }
+
+    //same layout as the save trailer plus the latch sequence, the clock source stays as it is
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.save());
+        w.write_bool(self.latch_armed);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), GameboyError> {
+        if !self.load(r.read_bytes()?) {
+            return Err(GameboyError::BadSaveState { reason: String::from("bad rtc block") });
+        }
+        self.latch_armed = r.read_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    const START_TIME: u64 = 1_700_000_000;
+
+    struct FakeClock(Rc<Cell<u64>>);
+
+    impl RtcClock for FakeClock {
+        fn now(&self) -> u64 {
+            self.0.get()
+        }
+    }
+
+    fn rtc_with_fake_clock() -> (Rtc, Rc<Cell<u64>>) {
+        let time = Rc::new(Cell::new(START_TIME));
+        let mut rtc = Rtc::new();
+        rtc.set_clock(Box::new(FakeClock(time.clone())));
+        (rtc, time)
+    }
+
+    fn advance(time: &Cell<u64>, secs: u64) {
+        time.set(time.get() + secs);
+    }
+
+    fn latch(rtc: &mut Rtc) {
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x01);
+    }
+
+    #[test]
+    fn registers_only_change_on_latch() {
+        let (mut rtc, time) = rtc_with_fake_clock();
+        advance(&time, 5);
+        assert_eq!(rtc.read_register(RTC_SECONDS), 0);
+
+        latch(&mut rtc);
+        assert_eq!(rtc.read_register(RTC_SECONDS), 5);
+
+        advance(&time, 3);
+        assert_eq!(rtc.read_register(RTC_SECONDS), 5);
+
+        //0x01 on its own doesn't latch, it has to follow a 0x00
+        rtc.write_latch(0x01);
+        assert_eq!(rtc.read_register(RTC_SECONDS), 5);
+        rtc.write_latch(0x02);
+        rtc.write_latch(0x01);
+        assert_eq!(rtc.read_register(RTC_SECONDS), 5);
+
+        latch(&mut rtc);
+        assert_eq!(rtc.read_register(RTC_SECONDS), 8);
+    }
+
+    #[test]
+    fn halt_stops_the_clock() {
+        let (mut rtc, time) = rtc_with_fake_clock();
+        advance(&time, 10);
+        rtc.write_register(RTC_DAYS_HIGH, DH_HALT);
+        advance(&time, 100);
+        latch(&mut rtc);
+        assert_eq!(rtc.read_register(RTC_SECONDS), 10);
+        assert_eq!(rtc.read_register(RTC_DAYS_HIGH), DH_HALT);
+
+        rtc.write_register(RTC_DAYS_HIGH, 0x00);
+        advance(&time, 7);
+        latch(&mut rtc);
+        assert_eq!(rtc.read_register(RTC_SECONDS), 17);
+        assert_eq!(rtc.read_register(RTC_DAYS_HIGH), 0x00);
+    }
+
+    #[test]
+    fn day_counter_overflow_sets_the_carry() {
+        let (mut rtc, time) = rtc_with_fake_clock();
+        rtc.write_register(RTC_DAYS_LOW, 0xff);
+        rtc.write_register(RTC_HOURS, 23);
+        rtc.write_register(RTC_MINUTES, 59);
+        rtc.write_register(RTC_SECONDS, 59);
+        rtc.write_register(RTC_DAYS_HIGH, DH_DAY_BIT_8);
+
+        advance(&time, 1);
+        latch(&mut rtc);
+        assert_eq!(rtc.read_register(RTC_SECONDS), 0);
+        assert_eq!(rtc.read_register(RTC_MINUTES), 0);
+        assert_eq!(rtc.read_register(RTC_HOURS), 0);
+        assert_eq!(rtc.read_register(RTC_DAYS_LOW), 0);
+        assert_eq!(rtc.read_register(RTC_DAYS_HIGH), DH_DAY_CARRY);
+
+        //the carry sticks until the game clears it
+        advance(&time, 24 * 60 * 60);
+        latch(&mut rtc);
+        assert_eq!(rtc.read_register(RTC_DAYS_LOW), 1);
+        assert_eq!(rtc.read_register(RTC_DAYS_HIGH), DH_DAY_CARRY);
+
+        rtc.write_register(RTC_DAYS_HIGH, 0x00);
+        latch(&mut rtc);
+        assert_eq!(rtc.read_register(RTC_DAYS_HIGH), 0x00);
+    }
+
+    #[test]
+    fn save_trailer_round_trip() {
+        let (mut rtc, time) = rtc_with_fake_clock();
+        rtc.write_register(RTC_HOURS, 5);
+        rtc.write_register(RTC_DAYS_LOW, 0x34);
+        rtc.write_register(RTC_DAYS_HIGH, DH_DAY_BIT_8 | DH_DAY_CARRY);
+        advance(&time, 62);
+        latch(&mut rtc);
+        advance(&time, 30);
+
+        let data = rtc.save();
+        assert_eq!(data.len(), RTC_SAVE_SIZE);
+        //stamped with the last time the live registers were updated, the latch here
+        assert_eq!(u64::from_le_bytes(data[40..48].try_into().unwrap()), START_TIME + 62);
+
+        let (mut loaded, _) = rtc_with_fake_clock();
+        loaded.set_clock(Box::new(FakeClock(time.clone())));
+        assert!(loaded.load(&data));
+        for select in RTC_SECONDS..=RTC_DAYS_HIGH {
+            assert_eq!(loaded.read_register(select), rtc.read_register(select));
+        }
+
+        latch(&mut loaded);
+        assert_eq!(loaded.read_register(RTC_SECONDS), 32);
+        assert_eq!(loaded.read_register(RTC_MINUTES), 1);
+    }
+
+    #[test]
+    fn loads_the_short_trailer() {
+        let (mut rtc, time) = rtc_with_fake_clock();
+        rtc.write_register(RTC_MINUTES, 10);
+        let mut data = rtc.save();
+        data.truncate(40);
+        data.extend_from_slice(&(START_TIME as u32).to_le_bytes());
+        assert_eq!(data.len(), RTC_SAVE_SIZE_SHORT_TIMESTAMP);
+
+        //time that passed while the emulator was closed is caught up on load
+        advance(&time, 61);
+        let (mut loaded, _) = rtc_with_fake_clock();
+        loaded.set_clock(Box::new(FakeClock(time.clone())));
+        assert!(loaded.load(&data));
+        latch(&mut loaded);
+        assert_eq!(loaded.read_register(RTC_SECONDS), 1);
+        assert_eq!(loaded.read_register(RTC_MINUTES), 11);
+
+        assert!(!loaded.load(&data[..43]));
+    }
+}
@@ -0,0 +1,129 @@
+use super::error::GameboyError;
+
+//"LGBS", followed by a u32 format version
+pub const SAVE_STATE_MAGIC: [u8; 4] = *b"LGBS";
+pub const SAVE_STATE_VERSION: u32 = 4;
+
+//little endian writer used by each component to append its state
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter { data: Vec::new() }
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_i32(&mut self, value: i32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u128(&mut self, value: u128) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    //length prefixed, so a reader can check it against the buffer it is restoring into
+    pub fn write_bytes(&mut self, value: &[u8]) {
+        self.write_u32(value.len() as u32);
+        self.data.extend_from_slice(value);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> StateReader<'a> {
+        StateReader { data, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], GameboyError> {
+        if self.data.len() - self.pos < len {
+            return Err(GameboyError::BadSaveState { reason: String::from("unexpected end of data") });
+        }
+        let ret = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(ret)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, GameboyError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, GameboyError> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, GameboyError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, GameboyError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32, GameboyError> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, GameboyError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn read_u128(&mut self) -> Result<u128, GameboyError> {
+        Ok(u128::from_le_bytes(self.take(16)?.try_into().unwrap()))
+    }
+
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], GameboyError> {
+        let len = self.read_u32()? as usize;
+        self.take(len)
+    }
+
+    //reads a length prefixed block into an existing buffer, which must be the same size
+    pub fn read_bytes_into(&mut self, out: &mut [u8], what: &str) -> Result<(), GameboyError> {
+        let data = self.read_bytes()?;
+        if data.len() != out.len() {
+            return Err(GameboyError::BadSaveState {
+                reason: format!("{} is {:#x} bytes, expected {:#x}", what, data.len(), out.len()),
+            });
+        }
+        out.copy_from_slice(data);
+        Ok(())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.pos == self.data.len()
+    }
+}
@@ -0,0 +1,191 @@
+use std::sync::{Arc, Mutex};
+
+use super::error::GameboyError;
+use super::save_state::{StateReader, StateWriter};
+use super::{OtherState, INT_SERIAL};
+
+const SC_TRANSFER_START: u8 = 0x80;
+const SC_INTERNAL_CLOCK: u8 = 0x01;
+const SC_UNUSED_BITS: u8 = 0x7e;
+
+//the internal clock shifts one bit on each falling edge of this div bit (8192Hz)
+const SERIAL_CLOCK_DIV_BIT: u16 = 0x100;
+
+//whatever is on the other end of the link cable
+pub trait LinkEndpoint {
+    //called when this side starts an internally clocked transfer, returns the byte the other
+    //side shifts back, 0xff when nothing is listening
+    fn exchange_as_master(&mut self, out: u8) -> u8;
+
+    //called every step while waiting on an external clock, returns the byte the other side
+    //clocked in once it has driven a whole transfer, `out` being what we shift back
+    fn poll_as_slave(&mut self, out: u8) -> Option<u8>;
+
+    //called every step with the cycles that passed and the current sb, for endpoints that
+    //keep time with the other side
+    fn tick(&mut self, _cycles: u32, _data: u8) {}
+}
+
+//no cable plugged in, the line floats high and nothing ever clocks us
+pub struct DisconnectedLink;
+
+impl LinkEndpoint for DisconnectedLink {
+    fn exchange_as_master(&mut self, _out: u8) -> u8 {
+        0xff
+    }
+
+    fn poll_as_slave(&mut self, _out: u8) -> Option<u8> {
+        None
+    }
+}
+
+//records every byte sent as master, handy for test roms that print over serial
+#[derive(Clone, Default)]
+pub struct SerialBuffer {
+    bytes: Arc<Mutex<Vec<u8>>>,
+}
+
+impl SerialBuffer {
+    pub fn new() -> SerialBuffer {
+        SerialBuffer::default()
+    }
+
+    //returns the bytes sent since the last call
+    pub fn take_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.bytes.lock().unwrap();
+        std::mem::take(&mut *bytes)
+    }
+}
+
+impl LinkEndpoint for SerialBuffer {
+    fn exchange_as_master(&mut self, out: u8) -> u8 {
+        self.bytes.lock().unwrap().push(out);
+        0xff
+    }
+
+    fn poll_as_slave(&mut self, _out: u8) -> Option<u8> {
+        None
+    }
+}
+
+pub struct Serial {
+    data: u8, //sb
+    transfer_active: bool, //sc
+    internal_clock: bool,
+
+    incoming: u8,
+    bits_left: u8,
+    last_div_bit: bool,
+
+    link: Box<dyn LinkEndpoint>,
+}
+
+impl Default for Serial {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Serial {
+    pub fn new() -> Serial {
+        Serial {
+            data: 0,
+            transfer_active: false,
+            internal_clock: false,
+
+            incoming: 0xff,
+            bits_left: 0,
+            last_div_bit: false,
+
+            link: Box::new(DisconnectedLink),
+        }
+    }
+
+    pub fn set_link(&mut self, link: Box<dyn LinkEndpoint>) {
+        self.link = link;
+    }
+
+    pub fn read_data(&self) -> u8 {
+        self.data
+    }
+
+    pub fn write_data(&mut self, value: u8) {
+        self.data = value;
+    }
+
+    pub fn read_control(&self) -> u8 {
+        let mut val = SC_UNUSED_BITS;
+        if self.transfer_active {
+            val |= SC_TRANSFER_START;
+        }
+        if self.internal_clock {
+            val |= SC_INTERNAL_CLOCK;
+        }
+        val
+    }
+
+    pub fn write_control(&mut self, value: u8) {
+        self.transfer_active = value & SC_TRANSFER_START != 0;
+        self.internal_clock = value & SC_INTERNAL_CLOCK != 0;
+
+        if self.transfer_active && self.internal_clock {
+            //the whole byte is swapped up front, then shifted in one bit per serial clock
+            self.incoming = self.link.exchange_as_master(self.data);
+            self.bits_left = 8;
+        }
+    }
+
+    fn finish_transfer(&mut self, other_state: &mut OtherState) {
+        self.transfer_active = false;
+        self.bits_left = 0;
+        other_state.int_flag |= INT_SERIAL;
+    }
+
+    pub fn run_cycles(&mut self, cycles: u32, other_state: &mut OtherState) {
+        self.link.tick(cycles, self.data);
+
+        let div_bit = other_state.counter_div.0 & SERIAL_CLOCK_DIV_BIT != 0;
+        let falling_edge = self.last_div_bit && !div_bit;
+        self.last_div_bit = div_bit;
+
+        if !self.transfer_active {
+            return;
+        }
+
+        if !self.internal_clock {
+            if let Some(byte) = self.link.poll_as_slave(self.data) {
+                self.data = byte;
+                self.finish_transfer(other_state);
+            }
+            return;
+        }
+
+        if falling_edge && self.bits_left > 0 {
+            self.bits_left -= 1;
+            self.data = (self.data << 1) | ((self.incoming >> self.bits_left) & 0x1);
+            if self.bits_left == 0 {
+                self.finish_transfer(other_state);
+            }
+        }
+    }
+
+    //the link endpoint is host side and is not part of the state
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.data);
+        w.write_bool(self.transfer_active);
+        w.write_bool(self.internal_clock);
+        w.write_u8(self.incoming);
+        w.write_u8(self.bits_left);
+        w.write_bool(self.last_div_bit);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), GameboyError> {
+        self.data = r.read_u8()?;
+        self.transfer_active = r.read_bool()?;
+        self.internal_clock = r.read_bool()?;
+        self.incoming = r.read_u8()?;
+        self.bits_left = r.read_u8()?.min(8);
+        self.last_div_bit = r.read_bool()?;
+        Ok(())
+    }
+}
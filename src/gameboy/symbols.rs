@@ -0,0 +1,159 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use log::{info, warn};
+
+use super::disassembler::Instruction;
+use super::error::GameboyError;
+use super::{read_file, Gameboy};
+
+//labels from an rgbds or no$gmb .sym file, one "bank:address label" entry per line with ;
+//starting a comment. only the switchable rom area is told apart by bank, everywhere else a
+//dmg has one bank and the number in the file is ignored
+pub struct SymbolTable {
+    addrs: HashMap<String, (usize, u16)>,
+    labels: BTreeMap<(usize, u16), String>,
+}
+
+fn key(bank: usize, addr: u16) -> (usize, u16) {
+    if (0x4000..0x8000).contains(&addr) {
+        (bank, addr)
+    } else {
+        (0, addr)
+    }
+}
+
+//a label only covers addresses in the same part of the memory map
+fn region(addr: u16) -> u8 {
+    match addr {
+        0x0000..=0x3fff => 0,
+        0x4000..=0x7fff => 1,
+        0x8000..=0x9fff => 2,
+        0xa000..=0xbfff => 3,
+        0xc000..=0xfdff => 4,
+        0xfe00..=0xff7f => 5,
+        _ => 6,
+    }
+}
+
+impl SymbolTable {
+    pub fn parse(text: &str) -> SymbolTable {
+        let mut table = SymbolTable {
+            addrs: HashMap::new(),
+            labels: BTreeMap::new(),
+        };
+
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let entry = line.split_once(char::is_whitespace).and_then(|(location, name)| {
+                let (bank, addr) = location.split_once(':')?;
+                let bank = usize::from_str_radix(bank, 16).ok()?;
+                let addr = u16::from_str_radix(addr, 16).ok()?;
+                Some((bank, addr, name.trim()))
+            });
+            match entry {
+                Some((bank, addr, name)) => {
+                    table.addrs.insert(String::from(name), (bank, addr));
+                    //the first label at an address wins, later ones are usually locals
+                    table.labels.entry(key(bank, addr)).or_insert_with(|| String::from(name));
+                }
+                None => warn!("Skipping bad symbol on line {}: {}", line_number + 1, line),
+            }
+        }
+        table
+    }
+
+    pub fn load(path: &Path) -> Result<SymbolTable, GameboyError> {
+        let data = read_file(&path.to_string_lossy())?;
+        Ok(SymbolTable::parse(&String::from_utf8_lossy(&data)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.addrs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addrs.is_empty()
+    }
+
+    //bank and address of a label
+    pub fn lookup(&self, name: &str) -> Option<(usize, u16)> {
+        self.addrs.get(name).copied()
+    }
+
+    pub fn label_at(&self, bank: usize, addr: u16) -> Option<&str> {
+        self.labels.get(&key(bank, addr)).map(|name| name.as_str())
+    }
+
+    //closest label at or before addr, with the distance from it added on
+    pub fn symbolize(&self, bank: usize, addr: u16) -> Option<String> {
+        let wanted = key(bank, addr);
+        let (&(label_bank, label_addr), name) = self.labels.range(..=wanted).next_back()?;
+        if label_bank != wanted.0 || region(label_addr) != region(addr) {
+            return None;
+        }
+        match addr - label_addr {
+            0 => Some(name.clone()),
+            offset => Some(format!("{}+${:x}", name, offset)),
+        }
+    }
+
+    //the instruction with the label of whatever it jumps to or loads from as a comment.
+    //`romx_bank` is the bank switched in at 0x4000-0x7fff
+    pub fn annotate(&self, instr: &Instruction, romx_bank: usize) -> String {
+        match instr.target.and_then(|target| self.symbolize(romx_bank, target)) {
+            Some(label) => format!("{:<32}; {}", instr.to_string(), label),
+            None => instr.to_string(),
+        }
+    }
+}
+
+impl Gameboy {
+    pub fn load_symbols(&mut self, path: &Path) -> Result<(), GameboyError> {
+        let symbols = SymbolTable::load(path)?;
+        info!("Loaded {} symbols from {}", symbols.len(), path.display());
+        self.symbols = Some(symbols);
+        Ok(())
+    }
+
+    pub fn set_symbols(&mut self, symbols: Option<SymbolTable>) {
+        self.symbols = symbols;
+    }
+
+    pub fn get_symbols(&self) -> Option<&SymbolTable> {
+        self.symbols.as_ref()
+    }
+
+    //label for an address as the cpu currently sees it, using whichever rom bank the mapper
+    //has switched in
+    pub fn symbolize(&self, addr: u16) -> Option<String> {
+        self.symbols.as_ref()?.symbolize(self.rom.rom_bank(addr), addr)
+    }
+
+    //address of a label, plus the bank when it lives in switchable rom
+    pub fn resolve_symbol(&self, name: &str) -> Option<(Option<usize>, u16)> {
+        let (bank, addr) = self.symbols.as_ref()?.lookup(name)?;
+        Some(((0x4000..0x8000).contains(&addr).then_some(bank), addr))
+    }
+
+    //disassembles one instruction, with a label line above it when one starts there
+    pub fn disassemble_labelled(&mut self, addr: u16) -> String {
+        let instr = self.disassemble_at(addr);
+        let bank = self.rom.rom_bank(addr);
+        let symbols = match &self.symbols {
+            Some(symbols) => symbols,
+            None => return format!("{:02x}:{}", bank, instr),
+        };
+
+        let romx_bank = self.rom.rom_bank(0x4000);
+        let line = format!("{:02x}:{}", bank, symbols.annotate(&instr, romx_bank));
+        match symbols.label_at(bank, addr) {
+            Some(label) => format!("{}:\n{}", label, line),
+            None => line,
+        }
+    }
+}
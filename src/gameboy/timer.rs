@@ -0,0 +1,224 @@
+use core::num::Wrapping as W;
+
+use super::{OtherState, INT_TIMER};
+
+const TAC_ENABLE: u8 = 0x4;
+const TAC_CLOCK_SELECT: u8 = 0x3;
+
+//bit of the internal div counter that clocks tima for each tac clock select value
+const TAC_DIV_BITS: [u16; 4] = [9, 3, 5, 7];
+
+fn timer_signal(other_state: &OtherState) -> bool {
+    if other_state.counter_tac & TAC_ENABLE == 0 {
+        return false;
+    }
+
+    let div_bit = TAC_DIV_BITS[(other_state.counter_tac & TAC_CLOCK_SELECT) as usize];
+    (other_state.counter_div.0 >> div_bit) & 0x1 != 0
+}
+
+fn increment_tima(other_state: &mut OtherState) {
+    if other_state.counter_tima.0 == 0xff {
+        //tima reads as 0 for one m-cycle before being reloaded from tma
+        other_state.counter_tima = W(0);
+        other_state.tima_reload_pending = true;
+    } else {
+        other_state.counter_tima += 1;
+    }
+}
+
+pub fn tick(other_state: &mut OtherState, cycles: u32) {
+    for _ in 0..(cycles / 4) {
+        other_state.tima_reloading = false;
+        if other_state.tima_reload_pending {
+            other_state.tima_reload_pending = false;
+            other_state.tima_reloading = true;
+            other_state.counter_tima = W(other_state.counter_tma);
+            other_state.int_flag |= INT_TIMER;
+        }
+
+        let old_signal = timer_signal(other_state);
+        other_state.counter_div += 4;
+        if old_signal && !timer_signal(other_state) {
+            increment_tima(other_state);
+        }
+    }
+}
+
+pub fn read_tac(other_state: &OtherState) -> u8 {
+    0b1111_1000 | other_state.counter_tac
+}
+
+pub fn write_div(other_state: &mut OtherState) {
+    //resetting div can cause a falling edge on the selected bit
+    let old_signal = timer_signal(other_state);
+    other_state.counter_div = W(0);
+    if old_signal {
+        increment_tima(other_state);
+    }
+}
+
+pub fn write_tima(other_state: &mut OtherState, value: u8) {
+    if other_state.tima_reloading {
+        //writes during the reload cycle are overwritten by tma
+        return;
+    }
+
+    //writing during the overflow cycle cancels the reload and the interrupt
+    other_state.tima_reload_pending = false;
+    other_state.counter_tima = W(value);
+}
+
+pub fn write_tma(other_state: &mut OtherState, value: u8) {
+    other_state.counter_tma = value;
+    if other_state.tima_reloading {
+        other_state.counter_tima = W(value);
+    }
+}
+
+pub fn write_tac(other_state: &mut OtherState, value: u8) {
+    //on dmg, disabling the timer or switching clocks while the selected bit is high increments tima
+    let old_signal = timer_signal(other_state);
+    other_state.counter_tac = value & (TAC_ENABLE | TAC_CLOCK_SELECT);
+    if old_signal && !timer_signal(other_state) {
+        increment_tima(other_state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //16 cycle clock, tima is clocked by the falling edge of div bit 3
+    const TAC_ENABLED_16: u8 = TAC_ENABLE | 0x1;
+    const TMA: u8 = 0x23;
+
+    //timer one m-cycle before div bit 3 falls
+    fn timer_before_edge(tima: u8) -> OtherState {
+        let mut other_state = OtherState::new();
+        other_state.counter_tac = TAC_ENABLED_16;
+        other_state.counter_tma = TMA;
+        other_state.counter_tima = W(tima);
+        other_state.counter_div = W(0x000c);
+        other_state
+    }
+
+    fn timer_interrupt_requested(other_state: &OtherState) -> bool {
+        other_state.int_flag & INT_TIMER != 0
+    }
+
+    #[test]
+    fn overflow_reloads_tma_one_cycle_late() {
+        let mut other_state = timer_before_edge(0xff);
+
+        tick(&mut other_state, 4);
+        assert_eq!(other_state.counter_tima.0, 0x00);
+        assert!(!timer_interrupt_requested(&other_state));
+
+        tick(&mut other_state, 4);
+        assert_eq!(other_state.counter_tima.0, TMA);
+        assert!(timer_interrupt_requested(&other_state));
+    }
+
+    #[test]
+    fn tima_write_in_the_overflow_cycle_cancels_the_reload() {
+        let mut other_state = timer_before_edge(0xff);
+
+        tick(&mut other_state, 4);
+        write_tima(&mut other_state, 0x50);
+
+        tick(&mut other_state, 4);
+        assert_eq!(other_state.counter_tima.0, 0x50);
+        assert!(!timer_interrupt_requested(&other_state));
+    }
+
+    #[test]
+    fn tima_write_in_the_reload_cycle_is_ignored() {
+        let mut other_state = timer_before_edge(0xff);
+
+        tick(&mut other_state, 8);
+        write_tima(&mut other_state, 0x50);
+        assert_eq!(other_state.counter_tima.0, TMA);
+
+        //the cycle after the reload takes writes again
+        tick(&mut other_state, 4);
+        write_tima(&mut other_state, 0x50);
+        assert_eq!(other_state.counter_tima.0, 0x50);
+        assert!(timer_interrupt_requested(&other_state));
+    }
+
+    #[test]
+    fn tma_write_in_the_reload_cycle_also_loads_tima() {
+        let mut other_state = timer_before_edge(0xff);
+
+        tick(&mut other_state, 8);
+        write_tma(&mut other_state, 0x77);
+        assert_eq!(other_state.counter_tima.0, 0x77);
+
+        tick(&mut other_state, 4);
+        write_tma(&mut other_state, 0x11);
+        assert_eq!(other_state.counter_tima.0, 0x77);
+    }
+
+    #[test]
+    fn div_write_with_the_selected_bit_high_increments_tima() {
+        let mut other_state = timer_before_edge(0x10);
+
+        write_div(&mut other_state);
+        assert_eq!(other_state.counter_tima.0, 0x11);
+
+        //div restarts from 0, so bit 3 next falls 4 m-cycles later
+        tick(&mut other_state, 12);
+        assert_eq!(other_state.counter_tima.0, 0x11);
+        tick(&mut other_state, 4);
+        assert_eq!(other_state.counter_tima.0, 0x12);
+    }
+
+    #[test]
+    fn div_write_with_the_selected_bit_low_does_nothing() {
+        let mut other_state = timer_before_edge(0x10);
+        other_state.counter_div = W(0x0004);
+
+        write_div(&mut other_state);
+        assert_eq!(other_state.counter_tima.0, 0x10);
+    }
+
+    #[test]
+    fn div_write_overflow_reloads_on_the_next_cycle() {
+        let mut other_state = timer_before_edge(0xff);
+
+        write_div(&mut other_state);
+        assert_eq!(other_state.counter_tima.0, 0x00);
+        assert!(!timer_interrupt_requested(&other_state));
+
+        tick(&mut other_state, 4);
+        assert_eq!(other_state.counter_tima.0, TMA);
+        assert!(timer_interrupt_requested(&other_state));
+    }
+
+    #[test]
+    fn tac_disable_with_the_selected_bit_high_increments_tima() {
+        let mut other_state = timer_before_edge(0x10);
+
+        write_tac(&mut other_state, 0x00);
+        assert_eq!(other_state.counter_tima.0, 0x11);
+
+        //stopped timer no longer counts
+        tick(&mut other_state, 64);
+        assert_eq!(other_state.counter_tima.0, 0x11);
+    }
+
+    #[test]
+    fn tac_clock_switch_increments_tima_only_on_a_falling_edge() {
+        //bit 3 high, bit 5 low: switching to the 64 cycle clock is a falling edge
+        let mut other_state = timer_before_edge(0x10);
+        write_tac(&mut other_state, TAC_ENABLE | 0x2);
+        assert_eq!(other_state.counter_tima.0, 0x11);
+
+        //bit 3 and bit 5 both high: no edge
+        let mut other_state = timer_before_edge(0x10);
+        other_state.counter_div = W(0x0028);
+        write_tac(&mut other_state, TAC_ENABLE | 0x2);
+        assert_eq!(other_state.counter_tima.0, 0x10);
+    }
+}
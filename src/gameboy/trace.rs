@@ -0,0 +1,117 @@
+use core::num::Wrapping as W;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use log::{error, info};
+
+use super::error::GameboyError;
+use super::Gameboy;
+
+//gameboy-doctor reference logs are made with LY reading this value
+const DOCTOR_LY: u8 = 0x90;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TraceOptions {
+    //start logging once the bootrom has unmapped itself, the doctor logs begin at 0x0100
+    pub skip_bootrom: bool,
+    //append the number of t-cycles run so far
+    pub cycles: bool,
+    //append the current LY
+    pub ly: bool,
+    //make LY always read 0x90 like the emulator the doctor logs were made with
+    pub doctor_ly: bool,
+}
+
+//writes one line per instruction in the gameboy-doctor format, before the instruction runs:
+//A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02
+pub struct TraceLogger {
+    writer: BufWriter<File>,
+    path: PathBuf,
+    options: TraceOptions,
+}
+
+impl TraceLogger {
+    fn write_line(&mut self, gb: &mut Gameboy) -> std::io::Result<()> {
+        let reg = &gb.reg;
+        let (a, f, b, c, d, e, h, l) = (reg.a.0, reg.get_f().0, reg.b.0, reg.c.0, reg.d.0, reg.e.0, reg.h.0, reg.l.0);
+        let pc = gb.pc;
+        let pcmem: Vec<u8> = (0..4).map(|i| gb.read_byte_raw(pc + W(i)).0).collect();
+
+        write!(
+            self.writer,
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            a, f, b, c, d, e, h, l, gb.sp.0, pc.0, pcmem[0], pcmem[1], pcmem[2], pcmem[3]
+        )?;
+        if self.options.cycles {
+            write!(self.writer, " CY:{}", gb.cycles_run)?;
+        }
+        if self.options.ly {
+            write!(self.writer, " LY:{:02X}", gb.read_ly())?;
+        }
+        writeln!(self.writer)
+    }
+}
+
+impl Gameboy {
+    //starts writing a trace to `path`, replacing any trace already running
+    pub fn start_trace(&mut self, path: &Path, options: TraceOptions) -> Result<(), GameboyError> {
+        self.stop_trace()?;
+        let file = File::create(path).map_err(|source| GameboyError::FileWrite {
+            path: path.to_string_lossy().into_owned(),
+            source,
+        })?;
+        info!("Writing instruction trace to {}", path.display());
+        self.trace = Some(TraceLogger {
+            writer: BufWriter::new(file),
+            path: path.to_path_buf(),
+            options,
+        });
+        self.doctor_ly = options.doctor_ly;
+        Ok(())
+    }
+
+    //flushes and closes the trace file
+    pub fn stop_trace(&mut self) -> Result<(), GameboyError> {
+        self.doctor_ly = false;
+        match self.trace.take() {
+            Some(mut trace) => trace.writer.flush().map_err(|source| GameboyError::FileWrite {
+                path: trace.path.to_string_lossy().into_owned(),
+                source,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.trace.is_some()
+    }
+
+    //LY as the cpu would read it
+    pub(super) fn read_ly(&self) -> u8 {
+        if self.doctor_ly {
+            return DOCTOR_LY;
+        }
+        self.ppu.get_current_y()
+    }
+}
+
+//logs the instruction about to run, a failed write ends the trace instead of the emulation
+pub(super) fn log_instruction(gb: &mut Gameboy) {
+    let mut trace = match gb.trace.take() {
+        Some(trace) => trace,
+        None => return,
+    };
+    if trace.options.skip_bootrom && gb.other_state.bootrom_enabled {
+        gb.trace = Some(trace);
+        return;
+    }
+
+    match trace.write_line(gb) {
+        Ok(()) => gb.trace = Some(trace),
+        Err(e) => {
+            error!("Stopped writing trace to {}: {}", trace.path.display(), e);
+            gb.doctor_ly = false;
+        }
+    }
+}
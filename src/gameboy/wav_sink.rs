@@ -0,0 +1,97 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use log::error;
+
+use super::apu::AudioSink;
+use super::error::GameboyError;
+
+const CHANNELS: u16 = 2;
+const BITS_PER_SAMPLE: u16 = 16;
+const RIFF_SIZE_OFFSET: u64 = 4;
+const DATA_SIZE_OFFSET: u64 = 40;
+//the riff size field is the data size plus the rest of the header
+const MAX_DATA_SIZE: u32 = u32::MAX - 36;
+
+//records the apu output to a 16 bit stereo .wav file. the sizes in the header are only
+//known at the end, they get filled in when the sink is dropped
+pub struct WavSink {
+    writer: BufWriter<File>,
+    path: PathBuf,
+    data_size: u32,
+    failed: bool,
+}
+
+impl WavSink {
+    pub fn create(path: &Path, sample_rate: u32) -> Result<WavSink, GameboyError> {
+        let file_error = |source| GameboyError::FileWrite {
+            path: path.to_string_lossy().into_owned(),
+            source,
+        };
+        let mut writer = BufWriter::new(File::create(path).map_err(file_error)?);
+
+        let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+        let mut header = Vec::with_capacity(44);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&36u32.to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes()); //pcm
+        header.extend_from_slice(&CHANNELS.to_le_bytes());
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&0u32.to_le_bytes());
+        writer.write_all(&header).map_err(file_error)?;
+
+        Ok(WavSink {
+            writer,
+            path: path.to_path_buf(),
+            data_size: 0,
+            failed: false,
+        })
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.writer.seek(SeekFrom::Start(RIFF_SIZE_OFFSET))?;
+        self.writer.write_all(&self.data_size.saturating_add(36).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(DATA_SIZE_OFFSET))?;
+        self.writer.write_all(&self.data_size.to_le_bytes())?;
+        self.writer.flush()
+    }
+}
+
+impl AudioSink for WavSink {
+    fn push_sample(&mut self, left: f32, right: f32) {
+        if self.failed {
+            return;
+        }
+
+        let mut frame = [0u8; 4];
+        frame[0..2].copy_from_slice(&((left.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes());
+        frame[2..4].copy_from_slice(&((right.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes());
+        if self.data_size > MAX_DATA_SIZE - frame.len() as u32 {
+            error!("Stopped recording audio to {}: the wav file is full", self.path.display());
+            self.failed = true;
+            return;
+        }
+        match self.writer.write_all(&frame) {
+            Ok(()) => self.data_size += frame.len() as u32,
+            Err(e) => {
+                error!("Stopped recording audio to {}: {}", self.path.display(), e);
+                self.failed = true;
+            }
+        }
+    }
+}
+
+impl Drop for WavSink {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            error!("Failed to finish {}: {}", self.path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,27 @@
+pub mod gameboy;
+
+pub use gameboy::apu::{AudioSink, SampleBuffer};
+pub use gameboy::cart_header::{CartHeader, CgbSupport, Licensee, MapperType};
+pub use gameboy::debugger::{BreakReason, Breakpoint, Comparison, Condition, Debugger, Register, WatchKind, Watchpoint};
+pub use gameboy::disassembler::{disassemble, instruction_length, Instruction};
+pub use gameboy::error::GameboyError;
+#[cfg(feature = "singlestep")]
+pub use gameboy::flat_bus::BusAccess;
+pub use gameboy::gdb_stub::GdbStub;
+pub use gameboy::input::{InputKey, JoypadButton};
+pub use gameboy::link_cable::SocketLink;
+pub use gameboy::local_link::{local_link_pair, run_linked_frame, LinkedGameboys, LocalLinkEnd};
+pub use gameboy::ppu::{GB_SCREEN_HEIGHT, GB_SCREEN_WIDTH};
+pub use gameboy::printer::Printer;
+pub use gameboy::profiler::{CodeLocation, FunctionStats, InstructionStats, Profiler};
+pub use gameboy::registers::Registers;
+pub use gameboy::rewind::{RewindBuffer, DEFAULT_REWIND_FRAME_INTERVAL, DEFAULT_REWIND_SECONDS};
+pub use gameboy::rtc::{RtcClock, SystemRtcClock};
+pub use gameboy::serial::{DisconnectedLink, LinkEndpoint, SerialBuffer};
+pub use gameboy::symbols::SymbolTable;
+pub use gameboy::trace::TraceOptions;
+pub use gameboy::wav_sink::WavSink;
+pub use gameboy::{run_frame, step, CpuLockup, Gameboy, GameboyOptions, SystemType};
+
+#[cfg(feature = "frontend")]
+pub use gameboy::render::{Renderer, StateSlotAction};
@@ -1,20 +1,135 @@
-mod gameboy;
-
-use crate::gameboy::{Gameboy, SystemType};
-use gameboy::render::Renderer;
+use legumegb_rs::{
+    Gameboy, GameboyOptions, GdbStub, LinkedGameboys, Printer, Renderer, SocketLink, StateSlotAction, SystemType, DEFAULT_REWIND_FRAME_INTERVAL,
+    DEFAULT_REWIND_SECONDS, GB_SCREEN_HEIGHT, GB_SCREEN_WIDTH, TraceOptions, WavSink,
+};
 use log::{error, info};
 use simplelog::*;
 use std::{
     env, fs,
     fs::File,
-    io::{BufWriter, Write},
-    ops::BitAnd,
+    io,
+    io::{BufRead, Write},
+    path::Path,
+    sync::mpsc,
+    thread,
     time::Instant,
-    vec,
 };
 
-const GB_SCREEN_WIDTH: usize = 160;
-const GB_SCREEN_HEIGHT: usize = 144;
+const BATTERY_FLUSH_FRAMES: u128 = 300;
+const PROFILE_REPORT_ROWS: usize = 50;
+const AUDIO_SAMPLE_RATE: u32 = 48000;
+
+enum LinkMode {
+    Listen(String),
+    Connect(String),
+}
+
+//addresses starting with unix: are unix socket paths, anything else is host:port
+fn open_link(mode: &LinkMode) -> io::Result<SocketLink> {
+    match mode {
+        LinkMode::Listen(addr) => match addr.strip_prefix("unix:") {
+            #[cfg(unix)]
+            Some(path) => SocketLink::listen_unix(Path::new(path)),
+            #[cfg(not(unix))]
+            Some(_) => Err(io::Error::new(io::ErrorKind::Unsupported, "unix sockets are not available")),
+            None => SocketLink::listen_tcp(addr.as_str()),
+        },
+        LinkMode::Connect(addr) => match addr.strip_prefix("unix:") {
+            #[cfg(unix)]
+            Some(path) => SocketLink::connect_unix(Path::new(path)),
+            #[cfg(not(unix))]
+            Some(_) => Err(io::Error::new(io::ErrorKind::Unsupported, "unix sockets are not available")),
+            None => SocketLink::connect_tcp(addr.as_str()),
+        },
+    }
+}
+
+fn to_screen(frame: Vec<u32>) -> Vec<u32> {
+    if frame.len() == GB_SCREEN_WIDTH * GB_SCREEN_HEIGHT {
+        frame
+    } else {
+        vec![0u32; GB_SCREEN_WIDTH * GB_SCREEN_HEIGHT]
+    }
+}
+
+fn flush_battery(gb: &mut Gameboy) {
+    if let Err(e) = gb.flush_battery() {
+        error!("Failed to save battery ram: {}", e);
+    }
+}
+
+//reads debugger commands off stdin on its own thread so the window keeps running
+fn spawn_debug_prompt() -> mpsc::Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    print_prompt();
+    receiver
+}
+
+fn print_prompt() {
+    print!("(gb) ");
+    let _ = io::stdout().flush();
+}
+
+//runs every command typed since the last frame, false once the user asked to quit
+fn run_debug_commands(gb: &mut Gameboy, commands: &mpsc::Receiver<String>, was_paused: &mut bool) -> bool {
+    while let Ok(line) = commands.try_recv() {
+        if matches!(line.trim(), "quit" | "q") {
+            return false;
+        }
+        let output = gb.debug_command(&line);
+        if !output.is_empty() {
+            println!("{}", output);
+        }
+        *was_paused = gb.is_paused();
+        print_prompt();
+    }
+    true
+}
+
+//two cores linked in process, shown side by side
+fn run_linked(first: Gameboy, second: Gameboy) {
+    let mut linked = LinkedGameboys::new(first, second);
+    let mut renderer = Renderer::new_dual();
+
+    let mut first_frame = vec![0u32; GB_SCREEN_WIDTH * GB_SCREEN_HEIGHT];
+    let mut second_frame = vec![0u32; GB_SCREEN_WIDTH * GB_SCREEN_HEIGHT];
+    let mut frames_run: u128 = 0;
+
+    while renderer.process_frames(&[&first_frame, &second_frame]) {
+        frames_run += 1;
+        match legumegb_rs::run_linked_frame(&mut linked, &renderer.keys, &renderer.second_keys) {
+            Ok((first, second)) => {
+                first_frame = to_screen(first);
+                second_frame = to_screen(second);
+            }
+            Err(_) => {
+                break;
+            }
+        }
+
+        let status = match (linked.first.get_lockup(), linked.second.get_lockup()) {
+            (Some(lockup), _) => Some(format!("left CPU locked up, {}", lockup)),
+            (None, Some(lockup)) => Some(format!("right CPU locked up, {}", lockup)),
+            (None, None) => None,
+        };
+        renderer.set_status(status);
+
+        if frames_run.is_multiple_of(BATTERY_FLUSH_FRAMES) {
+            flush_battery(&mut linked.first);
+            flush_battery(&mut linked.second);
+        }
+    }
+
+    flush_battery(&mut linked.first);
+    flush_battery(&mut linked.second);
+}
 
 fn main() {
     env::set_var("RUST_BACKTRACE", "full");
@@ -35,36 +150,267 @@ fn main() {
     .unwrap();
 
     let args: Vec<String> = env::args().collect();
-    if args.len() != 3 {
+    if args.len() < 3 {
         error!(
-            "Arguments: {} <bootrom file> <rom file>",
+            "Arguments: {} <bootrom file> <rom file> [--allow-unsupported-mapper] [--rewind-seconds <n>] [--link-listen <addr>] [--link-connect <addr>] [--second-rom <rom file>] [--printer <output dir>] [--debug] [--gdb-port <port>] [--trace <file>] [--trace-skip-bootrom] [--trace-cycles] [--trace-ly] [--doctor-ly] [--profile <report file>] [--record-audio <wav file>]",
             args[0]
         );
         return;
     }
 
+    let mut options = GameboyOptions::default();
+    let mut rewind_seconds = DEFAULT_REWIND_SECONDS;
+    let mut link_mode = None;
+    let mut second_rom = None;
+    let mut printer_dir = None;
+    let mut debug = false;
+    let mut gdb_port = None;
+    let mut trace_path = None;
+    let mut trace_options = TraceOptions::default();
+    let mut profile_path = None;
+    let mut audio_path = None;
+    let mut extra_args = args[3..].iter();
+    while let Some(arg) = extra_args.next() {
+        match arg.as_str() {
+            "--allow-unsupported-mapper" => options.allow_unsupported_mapper = true,
+            "--debug" => debug = true,
+            "--trace" => match extra_args.next() {
+                Some(path) => trace_path = Some(path.clone()),
+                None => {
+                    error!("--trace needs a file to write the trace to");
+                    return;
+                }
+            },
+            "--profile" => match extra_args.next() {
+                Some(path) => profile_path = Some(path.clone()),
+                None => {
+                    error!("--profile needs a file to write the report to");
+                    return;
+                }
+            },
+            "--record-audio" => match extra_args.next() {
+                Some(path) => audio_path = Some(path.clone()),
+                None => {
+                    error!("--record-audio needs a .wav file to write to");
+                    return;
+                }
+            },
+            "--trace-skip-bootrom" => trace_options.skip_bootrom = true,
+            "--trace-cycles" => trace_options.cycles = true,
+            "--trace-ly" => trace_options.ly = true,
+            "--doctor-ly" => trace_options.doctor_ly = true,
+            "--gdb-port" => match extra_args.next().and_then(|v| v.parse::<u16>().ok()) {
+                Some(port) => gdb_port = Some(port),
+                None => {
+                    error!("--gdb-port needs a tcp port to listen on");
+                    return;
+                }
+            },
+            "--rewind-seconds" => match extra_args.next().and_then(|v| v.parse().ok()) {
+                Some(seconds) => rewind_seconds = seconds,
+                None => {
+                    error!("--rewind-seconds needs a number of seconds, 0 disables rewind");
+                    return;
+                }
+            },
+            "--link-listen" | "--link-connect" => {
+                let addr = match extra_args.next() {
+                    Some(addr) => addr.clone(),
+                    None => {
+                        error!("{} needs an address, host:port or unix:<path>", arg);
+                        return;
+                    }
+                };
+                link_mode = Some(if arg == "--link-listen" { LinkMode::Listen(addr) } else { LinkMode::Connect(addr) });
+            }
+            "--second-rom" => match extra_args.next() {
+                Some(path) => second_rom = Some(path.clone()),
+                None => {
+                    error!("--second-rom needs a rom file");
+                    return;
+                }
+            },
+            "--printer" => match extra_args.next() {
+                Some(dir) => printer_dir = Some(dir.clone()),
+                None => {
+                    error!("--printer needs a directory to write prints to");
+                    return;
+                }
+            },
+            _ => {
+                error!("Unknown option: {}", arg);
+                return;
+            }
+        }
+    }
+
+    let mut gb = match Gameboy::new_with_options(SystemType::DMG, &args[2], &args[1], &options) {
+        Ok(gb) => gb,
+        Err(e) => {
+            error!("Failed to start: {}", e);
+            return;
+        }
+    };
+    if link_mode.is_some() as u8 + second_rom.is_some() as u8 + printer_dir.is_some() as u8 > 1 {
+        error!("Only one of --link-listen, --link-connect, --second-rom and --printer can be used");
+        return;
+    }
+    //a paused core stops answering the link sync and the other side gives up on it
+    if link_mode.is_some() && (debug || gdb_port.is_some()) {
+        error!("--debug and --gdb-port can't be used with --link-listen or --link-connect");
+        return;
+    }
+
+    if let Some(path) = &trace_path {
+        if let Err(e) = gb.start_trace(Path::new(path), trace_options) {
+            error!("Failed to start trace: {}", e);
+            return;
+        }
+    }
+
+    if profile_path.is_some() {
+        gb.enable_profiler();
+    }
+
+    //recording only, live playback is left to programs using the library
+    if let Some(path) = &audio_path {
+        match WavSink::create(Path::new(path), AUDIO_SAMPLE_RATE) {
+            Ok(sink) => {
+                gb.set_audio_sample_rate(AUDIO_SAMPLE_RATE);
+                gb.set_audio_sink(Some(Box::new(sink)));
+            }
+            Err(e) => {
+                error!("Failed to start audio recording: {}", e);
+                return;
+            }
+        }
+    }
+
+    if let Some(dir) = &printer_dir {
+        match Printer::new(dir) {
+            Ok(printer) => gb.set_link_endpoint(Box::new(printer)),
+            Err(e) => {
+                error!("Failed to set up printer: {}", e);
+                return;
+            }
+        }
+    }
+
+    if let Some(second_rom) = &second_rom {
+        match Gameboy::new_with_options(SystemType::DMG, second_rom, &args[1], &options) {
+            Ok(second) => run_linked(gb, second),
+            Err(e) => error!("Failed to start second core: {}", e),
+        }
+        return;
+    }
+
+    if let Some(mode) = &link_mode {
+        match open_link(mode) {
+            Ok(link) => gb.set_link_endpoint(Box::new(link)),
+            Err(e) => {
+                error!("Failed to set up link cable: {}", e);
+                return;
+            }
+        }
+        //rewinding one side would desync the cable
+        rewind_seconds = 0;
+    }
+    if rewind_seconds > 0 {
+        gb.enable_rewind(rewind_seconds, DEFAULT_REWIND_FRAME_INTERVAL);
+    }
     let mut renderer = Renderer::new();
-    let mut gb = Gameboy::new(SystemType::DMG, &args[2], &args[1]);
+
+    let debug_commands = if debug {
+        gb.enable_debugger();
+        Some(spawn_debug_prompt())
+    } else {
+        None
+    };
+    let mut was_paused = false;
+    //only reachable from this machine, the protocol has no authentication
+    let mut gdb_stub = match gdb_port.map(|port| GdbStub::listen(("127.0.0.1", port))) {
+        Some(Ok(stub)) => Some(stub),
+        Some(Err(e)) => {
+            error!("Failed to start GDB server: {}", e);
+            return;
+        }
+        None => None,
+    };
 
     let mut last_frame = vec![0u32; GB_SCREEN_WIDTH * GB_SCREEN_HEIGHT];
-    let blank_frame = vec![0u32; GB_SCREEN_WIDTH * GB_SCREEN_HEIGHT];
     let mut frames_run: u128 = 0;
     let start_time = Instant::now();
 
     while renderer.process_frame(&last_frame) {
         frames_run += 1;
-        match gameboy::run_frame(&mut gb, &renderer.keys) {
+        if renderer.is_rewind_held() {
+            if gb.rewind_step() {
+                last_frame = gb.get_screen().to_vec();
+            }
+            continue;
+        }
+
+        match legumegb_rs::run_frame(&mut gb, &renderer.keys) {
             Ok(frame) => {
-                if frame.len() == GB_SCREEN_WIDTH * GB_SCREEN_HEIGHT {
-                    last_frame = frame;
-                } else {
-                    last_frame = blank_frame.clone();
-                }
+                last_frame = to_screen(frame);
             }
             Err(_) => {
                 break;
             }
         }
+        renderer.set_rumble(gb.is_rumble_active());
+        //the screen stays frozen on whatever was last drawn, same as the hardware
+        renderer.set_status(gb.get_lockup().map(|lockup| format!("CPU locked up, {}", lockup)));
+
+        if let Some(stub) = &mut gdb_stub {
+            stub.poll(&mut gb);
+        }
+        if let Some(commands) = &debug_commands {
+            if !run_debug_commands(&mut gb, commands, &mut was_paused) {
+                break;
+            }
+            if gb.is_paused() && !was_paused {
+                println!("\n{}", gb.describe_break());
+                print_prompt();
+            }
+            was_paused = gb.is_paused();
+        }
+        if gb.is_paused() && gb.get_lockup().is_none() {
+            renderer.set_status(Some(String::from("paused in debugger")));
+        }
+
+        match renderer.take_state_slot_action() {
+            Some(StateSlotAction::Save(slot)) => {
+                let path = Path::new(&args[2]).with_extension(format!("ss{}", slot));
+                match gb.save_state_to_file(&path) {
+                    Ok(()) => info!("Saved state to slot {}", slot),
+                    Err(e) => error!("Failed to save state: {}", e),
+                }
+            }
+            Some(StateSlotAction::Load(slot)) => {
+                let path = Path::new(&args[2]).with_extension(format!("ss{}", slot));
+                match gb.load_state_from_file(&path) {
+                    Ok(()) => info!("Loaded state from slot {}", slot),
+                    Err(e) => error!("Failed to load state: {}", e),
+                }
+            }
+            None => {}
+        }
+
+        if frames_run.is_multiple_of(BATTERY_FLUSH_FRAMES) {
+            flush_battery(&mut gb);
+        }
+    }
+
+    flush_battery(&mut gb);
+    if let Err(e) = gb.stop_trace() {
+        error!("Failed to finish trace: {}", e);
+    }
+    if let Some(path) = &profile_path {
+        match gb.write_profile_report(Path::new(path), PROFILE_REPORT_ROWS) {
+            Ok(()) => info!("Wrote profile to {}", path),
+            Err(e) => error!("Failed to write profile: {}", e),
+        }
     }
 
     let time_run = start_time.elapsed().as_secs_f64();